@@ -0,0 +1,172 @@
+use std::fmt::Display;
+use std::io::{self};
+use std::time::Duration;
+
+/// Result type used throughout the library.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors that can occur while scraping or converting books.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Request could not be completed.
+    Network { url: String, source: io::Error },
+    /// Server responded with an unsuccessful status code.
+    HttpStatus {
+        url: String,
+        status: u16,
+        retry_after: Option<Duration>,
+    },
+    /// Server refused to serve more requests, either with status 429 or a CAPTCHA page.
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+        captcha: bool,
+    },
+    /// Server responded with content other than what was requested, e.g. a web page in place of an image.
+    UnexpectedContent { url: String, content_type: String },
+    /// URL could not be understood.
+    InvalidUrl(String),
+    /// Output naming template could not be parsed.
+    InvalidTemplate(String),
+    /// Book page did not contain the expected metadata.
+    MetadataParse(String),
+    /// JSON did not match the expected schema.
+    JsonSchema(serde_json::Error),
+    /// Book has no pages that can be downloaded.
+    NoPagesAvailable { id: String, tld: String },
+    /// Image could not be decoded or encoded.
+    ImageDecode(image::ImageError),
+    /// Output file of the given format could not be written.
+    Writer {
+        format: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Reading or writing a local file failed.
+    Io(io::Error),
+}
+
+impl Error {
+    /// Returns true if trying the same operation again could succeed.
+    ///
+    /// Only network failures, rate limits, server errors and request timeouts are retried. Responses with the
+    /// wrong content are not, as CAPTCHA pages are reported as rate limits instead.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Network { .. } | Error::RateLimited { .. } => true,
+            Error::HttpStatus { status, .. } => (500..=599).contains(status) || matches!(status, 408 | 429),
+            _ => false,
+        }
+    }
+
+    /// Gets how long the server asked to wait before retrying, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::HttpStatus { retry_after, .. } | Error::RateLimited { retry_after, .. } => {
+                *retry_after
+            }
+            _ => None,
+        }
+    }
+
+    /// Gets a short name for the kind of error, e.g. "http_status", for recording alongside its message.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Network { .. } => "network",
+            Error::HttpStatus { .. } => "http_status",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::UnexpectedContent { .. } => "unexpected_content",
+            Error::InvalidUrl(_) => "invalid_url",
+            Error::InvalidTemplate(_) => "invalid_template",
+            Error::MetadataParse(_) => "metadata_parse",
+            Error::JsonSchema(_) => "json_schema",
+            Error::NoPagesAvailable { .. } => "no_pages_available",
+            Error::ImageDecode(_) => "image_decode",
+            Error::Writer { .. } => "writer",
+            Error::Io(_) => "io",
+        }
+    }
+
+    /// Creates a writer error from a message or underlying error.
+    pub(crate) fn writer(
+        format: &'static str,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Error {
+        Error::Writer {
+            format,
+            source: source.into(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Network { url, source } => write!(f, "request for {url} failed: {source}"),
+            Error::HttpStatus { url, status, .. } => {
+                write!(f, "server responded to {url} with status {status}")
+            }
+            Error::RateLimited { url, captcha, .. } => {
+                if *captcha {
+                    write!(f, "server responded to {url} with a CAPTCHA page, requests have been flagged as unusual traffic")
+                } else {
+                    write!(f, "rate limited by server when requesting {url}")
+                }
+            }
+            Error::UnexpectedContent { url, content_type } => {
+                write!(f, "unexpected content type \"{content_type}\" received from {url}")
+            }
+            Error::InvalidUrl(url) => write!(f, "invalid URL: {url}"),
+            Error::InvalidTemplate(msg) => write!(f, "invalid naming template: {msg}"),
+            Error::MetadataParse(msg) => write!(f, "{msg}"),
+            Error::JsonSchema(e) => write!(f, "unexpected JSON: {e}"),
+            Error::NoPagesAvailable { tld, .. } => match tld.as_str() {
+                ".com" => write!(f, "No downloadable pages found."),
+                _ => write!(f, "No downloadable pages found. Setting --tld-override to \".com\" may fix this issue."),
+            },
+            Error::ImageDecode(e) => write!(f, "image could not be processed: {e}"),
+            Error::Writer { format, source } => write!(f, "failed to write {format}: {source}"),
+            Error::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network { source, .. } => Some(source),
+            Error::JsonSchema(e) => Some(e),
+            Error::ImageDecode(e) => Some(e),
+            Error::Writer { source, .. } => Some(source.as_ref()),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::JsonSchema(value)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(value: image::ImageError) -> Self {
+        Error::ImageDecode(value)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::Io(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
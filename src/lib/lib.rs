@@ -1,2 +1,5 @@
+pub mod error;
 pub mod scraper;
 pub mod writer;
+
+pub use error::{Error, Result};
@@ -0,0 +1,414 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use super::helpers::{sibling_path, write_atomic};
+use super::types::ContentType;
+use crate::error::{Error, Result};
+
+/// Record of the books downloaded so far, so they can be skipped by later runs.
+///
+/// The archive file holds one JSON object per line, describing a book. Files in the older format, which only
+/// has one ID per line, are converted when opened.
+///
+/// Several processes can share an archive file. Reads and writes are guarded by an advisory lock on a `.lock`
+/// file beside it, and books being downloaded are claimed with lock files in a `.claims` directory beside it.
+#[derive(Debug, Default)]
+pub struct Archive {
+    path: PathBuf,
+    /// Latest entry for each book.
+    entries: Vec<ArchiveEntry>,
+    /// Index of the entry for each ID.
+    lookup: HashMap<String, usize>,
+}
+
+/// Book recorded in the archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveEntry {
+    /// ID of book.
+    pub id: String,
+    /// Outcome of download.
+    pub status: ArchiveStatus,
+    /// Full title of book, including the publish date of periodicals.
+    pub title: String,
+    /// Type of book, unknown for entries converted from the older format.
+    pub book_type: Option<ContentType>,
+    /// Date book was published, as displayed by Google Books.
+    pub publish_date: String,
+    /// Files generated from book.
+    pub outputs: Vec<ArchiveOutput>,
+    /// Number of pages downloaded.
+    pub page_count: Option<usize>,
+    /// Number of pages listed for book that couldn't be downloaded, e.g. those left out of previews.
+    pub pages_missing: Option<usize>,
+    /// Time download finished or failed, in RFC 3339 format.
+    pub completed_at: Option<String>,
+    /// URL book was downloaded from, recorded for failed downloads so they can be retried.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Directory book was being saved to, recorded for failed downloads so they can be retried.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_dir: Option<String>,
+    /// Kind of error that caused download to fail. See [`Error::kind`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    /// Message of error that caused download to fail.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Number of runs in a row in which download has failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+}
+
+/// Outcome of downloading a book.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveStatus {
+    /// Every page listed for book was downloaded.
+    #[default]
+    Complete,
+    /// Some pages listed for book couldn't be downloaded.
+    Partial,
+    /// Download failed and can be retried.
+    Failed,
+}
+
+/// Claim on a book being downloaded, released when dropped.
+///
+/// Claims are held with an advisory lock, so those left by a process that exits without releasing them are
+/// released by the OS.
+#[derive(Debug)]
+pub struct Claim {
+    path: PathBuf,
+    _file: File,
+}
+
+/// File generated from a book.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveOutput {
+    /// Format of file, e.g. "pdf".
+    pub format: String,
+    /// Path of file.
+    pub path: String,
+    /// SHA-256 of file, as lowercase hex.
+    pub sha256: String,
+}
+
+impl ArchiveEntry {
+    /// Creates an entry for a book completed now.
+    pub fn completed_now(id: &str) -> ArchiveEntry {
+        let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap_or_else(|_| OffsetDateTime::now_utc());
+        ArchiveEntry {
+            id: id.to_string(),
+            completed_at: now.format(&Rfc3339).ok(),
+            ..Default::default()
+        }
+    }
+
+    /// Gets the time download finished, if known.
+    pub fn completed_at(&self) -> Option<OffsetDateTime> {
+        OffsetDateTime::parse(self.completed_at.as_deref()?, &Rfc3339).ok()
+    }
+
+    /// Returns true if the book has been downloaded and can be skipped.
+    pub fn is_downloaded(&self) -> bool {
+        matches!(self.status, ArchiveStatus::Complete | ArchiveStatus::Partial)
+    }
+}
+
+impl Archive {
+    /// Opens the archive at the provided path, which need not exist yet, converting it from the older format if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Archive> {
+        // Only lock existing archives, as the directory of new ones may not exist yet.
+        let _lock = match path.as_ref().exists() {
+            true => Some(lock(path.as_ref())?),
+            false => None,
+        };
+        let (archive, migrated) = Self::read(path.as_ref())?;
+        if migrated {
+            archive.save()?;
+        }
+        Ok(archive)
+    }
+
+    /// Adds an entry to the archive and appends it to the archive file.
+    pub fn add(&mut self, entry: ArchiveEntry) -> Result<()> {
+        Self::append_entry(&self.path, &entry)?;
+        self.insert(entry);
+        Ok(())
+    }
+
+    /// Records a failed download in the archive file at the provided path, counting any earlier failures in a row.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of archive file.
+    /// * `id` - ID of book.
+    /// * `url` - URL book was downloaded from.
+    /// * `target_dir` - Directory book was being saved to.
+    /// * `error` - Error that caused download to fail.
+    pub fn record_failure(path: impl AsRef<Path>, id: &str, url: &str, target_dir: &str, error: &Error) -> Result<()> {
+        let _lock = lock(path.as_ref())?;
+        let previous_attempts = Self::read(path.as_ref())?
+            .0
+            .get(id)
+            .filter(|x| x.status == ArchiveStatus::Failed)
+            .and_then(|x| x.attempts)
+            .unwrap_or(0);
+        let entry = ArchiveEntry {
+            status: ArchiveStatus::Failed,
+            url: Some(url.to_string()),
+            target_dir: Some(target_dir.to_string()),
+            error_kind: Some(error.kind().to_string()),
+            error: Some(error.to_string()),
+            attempts: Some(previous_attempts + 1),
+            ..ArchiveEntry::completed_now(id)
+        };
+        Self::write_entry(path.as_ref(), &entry)
+    }
+
+    /// Appends an entry to the archive file at the provided path without reading it.
+    pub fn append_entry(path: impl AsRef<Path>, entry: &ArchiveEntry) -> Result<()> {
+        let _lock = lock(path.as_ref())?;
+        Self::write_entry(path.as_ref(), entry)
+    }
+
+    /// Claims a book so other processes sharing the archive file at the provided path skip it while it is
+    /// downloaded.
+    ///
+    /// Returns `None` if another claim on the book is held, whether by this process or another.
+    pub fn claim(path: impl AsRef<Path>, id: &str) -> Result<Option<Claim>> {
+        let claims_dir = sibling_path(path.as_ref(), "claims");
+        std::fs::create_dir_all(&claims_dir)?;
+        let claim_path = claims_dir.join(id);
+        let file = OpenOptions::new().write(true).create(true).truncate(false).open(&claim_path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Claim {
+                path: claim_path,
+                _file: file,
+            })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Gets the latest entry for a book.
+    pub fn get(&self, id: &str) -> Option<&ArchiveEntry> {
+        self.lookup.get(id).map(|&i| &self.entries[i])
+    }
+
+    /// Returns true if the book has been downloaded and can be skipped.
+    pub fn contains(&self, id: &str) -> bool {
+        self.get(id).is_some_and(|x| x.is_downloaded())
+    }
+
+    /// Gets the latest entry for each book, in the order they were first added.
+    pub fn entries(&self) -> impl Iterator<Item = &ArchiveEntry> {
+        self.entries.iter()
+    }
+
+    /// Gets the IDs of books that have been downloaded and can be skipped.
+    pub fn downloaded_ids(&self) -> impl Iterator<Item = &str> {
+        self.entries().filter(|x| x.is_downloaded()).map(|x| x.id.as_str())
+    }
+
+    /// Gets the entries of books whose latest download failed.
+    pub fn failed(&self) -> impl Iterator<Item = &ArchiveEntry> {
+        self.entries().filter(|x| x.status == ArchiveStatus::Failed)
+    }
+
+    /// Gets the entries of books with pages that couldn't be downloaded.
+    pub fn with_missing_pages(&self) -> impl Iterator<Item = &ArchiveEntry> {
+        self.entries().filter(|x| x.pages_missing.is_some_and(|x| x > 0))
+    }
+
+    /// Gets the entries of books completed within a time range, e.g. to find those added since a previous run.
+    pub fn completed_between(&self, start: OffsetDateTime, end: OffsetDateTime) -> impl Iterator<Item = &ArchiveEntry> {
+        self.entries().filter(move |x| x.completed_at().is_some_and(|x| start <= x && x < end))
+    }
+
+    /// Reads the archive file at the provided path, if it exists, without locking it, returning whether it needs
+    /// converting.
+    fn read(path: &Path) -> Result<(Archive, bool)> {
+        let mut archive = Archive {
+            path: path.to_path_buf(),
+            ..Default::default()
+        };
+        let mut migrated = false;
+        if !path.exists() {
+            return Ok((archive, migrated));
+        }
+        for (i, line) in std::fs::read_to_string(path)?.lines().map(|x| x.trim()).enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let entry = match line.starts_with('{') {
+                // Lines can be left incomplete by a process killed while writing, so skip rather than fail.
+                true => match serde_json::from_str(line) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        eprintln!("Skipping malformed line {} of {}: {}", i + 1, path.display(), e);
+                        continue;
+                    }
+                },
+                false => {
+                    migrated = true;
+                    ArchiveEntry {
+                        id: line.to_string(),
+                        ..Default::default()
+                    }
+                }
+            };
+            archive.insert(entry);
+        }
+        Ok((archive, migrated))
+    }
+
+    /// Appends an entry to the archive file at the provided path without locking it.
+    fn write_entry(path: &Path, entry: &ArchiveEntry) -> Result<()> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let mut line = std::format!("{}\n", serde_json::to_string(entry)?);
+
+        // Start a new line if the last one was left incomplete, so this entry isn't joined to it.
+        let mut last = [b'\n'];
+        if file.metadata()?.len() > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+        }
+        if last[0] != b'\n' {
+            line.insert(0, '\n');
+        }
+        Ok(file.write_all(line.as_bytes())?)
+    }
+
+    /// Records an entry in memory, replacing any earlier entry for the same book.
+    fn insert(&mut self, entry: ArchiveEntry) {
+        match self.lookup.get(&entry.id) {
+            Some(&i) => self.entries[i] = entry,
+            None => {
+                self.lookup.insert(entry.id.clone(), self.entries.len());
+                self.entries.push(entry);
+            }
+        }
+    }
+
+    /// Rewrites the archive file with the latest entry for each book.
+    fn save(&self) -> Result<()> {
+        let mut text = String::new();
+        for entry in self.entries() {
+            text.push_str(&serde_json::to_string(entry)?);
+            text.push('\n');
+        }
+        write_atomic(&self.path, text)
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        // Remove file while still locked. Any process that opened it before then can still claim it, but only once
+        // the outcome has been recorded in the archive.
+        _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Takes an exclusive lock on the archive file at the provided path, released when dropped.
+///
+/// The lock is held on a separate file, as the archive file itself is replaced when converted.
+fn lock(path: &Path) -> Result<File> {
+    let file = OpenOptions::new().write(true).create(true).truncate(false).open(sibling_path(path, "lock"))?;
+    file.lock()?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+
+    #[test]
+    fn migrate_and_query() {
+        let dir = fixtures::TempDir::new("archive");
+        let path = dir.join("archive.txt");
+        std::fs::write(&path, "CFEEAAAAMBAJ\n\nXV8XAAAAYAAJ\n").unwrap();
+
+        // Plain IDs are converted to entries and the file is rewritten.
+        let mut archive = Archive::open(&path).unwrap();
+        assert!(archive.contains("CFEEAAAAMBAJ"));
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with(r#"{"id":"CFEEAAAAMBAJ","#));
+
+        // Later entries replace earlier ones.
+        archive
+            .add(ArchiveEntry {
+                title: String::from("LIFE - Oct 3, 1969"),
+                book_type: Some(ContentType::Magazine),
+                status: ArchiveStatus::Partial,
+                page_count: Some(90),
+                pages_missing: Some(4),
+                ..ArchiveEntry::completed_now("CFEEAAAAMBAJ")
+            })
+            .unwrap();
+        let archive = Archive::open(&path).unwrap();
+        let ids: Vec<_> = archive.downloaded_ids().collect();
+        assert_eq!(ids, ["CFEEAAAAMBAJ", "XV8XAAAAYAAJ"]);
+        assert_eq!(archive.get("CFEEAAAAMBAJ").unwrap().page_count, Some(90));
+        let missing: Vec<_> = archive.with_missing_pages().map(|x| x.id.as_str()).collect();
+        assert_eq!(missing, ["CFEEAAAAMBAJ"]);
+
+        let now = OffsetDateTime::now_utc();
+        let recent: Vec<_> = archive
+            .completed_between(now - time::Duration::hours(1), now + time::Duration::hours(1))
+            .map(|x| x.id.as_str())
+            .collect();
+        assert_eq!(recent, ["CFEEAAAAMBAJ"]);
+    }
+
+    #[test]
+    fn skip_truncated_line() {
+        let dir = fixtures::TempDir::new("archive-truncated");
+        let path = dir.join("archive.jsonl");
+        std::fs::write(&path, "{\"id\":\"CFEEAAAAMBAJ\"}\n{\"id\":\"XV8X").unwrap();
+
+        // Incomplete line left by an interrupted write is skipped, and later entries aren't joined to it.
+        assert_eq!(Archive::open(&path).unwrap().entries().count(), 1);
+        Archive::append_entry(&path, &ArchiveEntry::completed_now("XV8XAAAAYAAJ")).unwrap();
+        let ids: Vec<_> = Archive::open(&path).unwrap().downloaded_ids().map(String::from).collect();
+        assert_eq!(ids, ["CFEEAAAAMBAJ", "XV8XAAAAYAAJ"]);
+    }
+
+    #[test]
+    fn claims_and_concurrent_writes() {
+        let dir = fixtures::TempDir::new("archive-claims");
+        let path = dir.join("archive.jsonl");
+
+        // Only one claim on a book is held at a time.
+        let claim = Archive::claim(&path, "CFEEAAAAMBAJ").unwrap();
+        assert!(claim.is_some());
+        assert!(Archive::claim(&path, "CFEEAAAAMBAJ").unwrap().is_none());
+        assert!(Archive::claim(&path, "XV8XAAAAYAAJ").unwrap().is_some());
+        drop(claim);
+        assert!(Archive::claim(&path, "CFEEAAAAMBAJ").unwrap().is_some());
+
+        // Entries appended at once by several writers are kept whole.
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for j in 0..25 {
+                        let entry = ArchiveEntry {
+                            title: "x".repeat(1000),
+                            ..ArchiveEntry::completed_now(&std::format!("BOOK{i}-{j}"))
+                        };
+                        Archive::append_entry(&path, &entry).unwrap();
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|x| x.join().unwrap());
+        assert_eq!(Archive::open(&path).unwrap().downloaded_ids().count(), 100);
+    }
+}
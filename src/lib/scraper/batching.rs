@@ -1,61 +1,94 @@
 use std::collections::HashSet;
-use std::io::{self};
 
-use ::scraper::{Html, Selector};
+use ::scraper::Html;
 
 use super::helpers::*;
 use super::types::*;
 use super::*;
+use crate::error::Result;
 
-/// Downloads all issues within the selected period of the page at the provided URL.
-pub fn download_period(url: &str, dest: &str, options: &ScraperOptions, already_downloaded: &mut HashSet<String>) -> io::Result<()> {
+/// Downloads all issues within the selected period of the page at the provided URL that match `options.filter`.
+pub fn download_period(url: &str, dest: &str, options: &ScraperOptions, already_downloaded: &mut HashSet<String>) -> Result<()> {
+    download_period_counted(url, dest, options, already_downloaded, &mut 0)
+}
+
+/// Downloads all issues within the series of the issue at the provided URL that match `options.filter`.
+pub fn download_all(url: &str, dest: &str, options: &ScraperOptions, already_downloaded: &mut HashSet<String>) -> Result<()> {
     let url = sanitize_url(url, Some(options))?;
 
     if options.verbose {
-        println!("Attempting download of period page with url: {url}");
+        println!("Attempting download of base page with url: {url}");
     }
 
-    for issue_url in get_issue_urls_in_period(&url, options)? {
-        if let Err(x) = download_issue_skip_downloaded(&issue_url, dest, options, Some(already_downloaded)) {
-            eprintln!("Error downloading issue {issue_url}: {}", x);
+    let mut downloaded = 0;
+    for period_url in get_period_urls(&url, options)? {
+        if options.filter.limit_reached(downloaded) {
+            break;
+        }
+        if let Err(x) = download_period_counted(&period_url, dest, options, already_downloaded, &mut downloaded) {
+            eprintln!("Error downloading period {period_url}: {}", x);
         }
     }
     Ok(())
 }
 
-/// Downloads all issues within the series of the issue at the provided URL.
-pub fn download_all(url: &str, dest: &str, options: &ScraperOptions, already_downloaded: &mut HashSet<String>) -> io::Result<()> {
+/// Downloads issues within the selected period of the page at the provided URL until the limit of `options.filter`
+/// is reached, adding the number downloaded to `downloaded`.
+fn download_period_counted(
+    url: &str,
+    dest: &str,
+    options: &ScraperOptions,
+    already_downloaded: &mut HashSet<String>,
+    downloaded: &mut usize,
+) -> Result<()> {
     let url = sanitize_url(url, Some(options))?;
 
     if options.verbose {
-        println!("Attempting download of base page with url: {url}");
+        println!("Attempting download of period page with url: {url}");
     }
 
-    for period_url in get_period_urls(&url, options)? {
-        if let Err(x) = download_period(&period_url, dest, options, already_downloaded) {
-            eprintln!("Error downloading period {period_url}: {}", x);
+    for issue in get_issues_in_period(&url, options)? {
+        if options.filter.limit_reached(*downloaded) {
+            if options.verbose {
+                println!("Reached limit of {} issue(s).", *downloaded);
+            }
+            break;
+        }
+
+        // Skip issues whose caption already shows they are out of range without fetching them.
+        if options.filter.matches_date(&PublishDate::parse(&issue.caption)) == Some(false) {
+            if options.verbose {
+                println!("Skipping issue outside date range: {} ({})", issue.id, issue.caption);
+            }
+            continue;
+        }
+
+        match download_issue_skip_downloaded(&issue.url, dest, options, Some(already_downloaded)) {
+            Ok(DownloadStatus::Complete(_)) => *downloaded += 1,
+            Ok(DownloadStatus::Skipped) => {}
+            Err(x) => eprintln!("Error downloading issue {}: {}", issue.url, x),
         }
     }
     Ok(())
 }
 
 /// Gets the URLs of available periods in the page at the provided URL.
-pub fn get_period_urls(url: &str, options: &ScraperOptions) -> io::Result<Vec<String>> {
+pub fn get_period_urls(url: &str, options: &ScraperOptions) -> Result<Vec<String>> {
     let mut ret = Vec::new();
 
-    let res = try_download(url, options.download_attempts)?;
-    let body = res.text().to_result()?;
+    let res = try_download(url, options)?;
+    let body = res.text();
 
     let doc = Html::parse_document(&body);
 
-    let selector = Selector::parse("#period_selector a").to_result()?;
+    let selector = selector("#period_selector a")?;
     for element in doc.select(&selector) {
         if let Some(x) = element.attr("href") {
             ret.push(if x.trim() == "" {
                 // Empty string used for current period.
                 url.to_string()
             } else {
-                x.to_string()
+                resolve_url(url, x)?
             });
         }
     }
@@ -69,19 +102,195 @@ pub fn get_period_urls(url: &str, options: &ScraperOptions) -> io::Result<Vec<St
 }
 
 /// Gets the URLs of issues within the selected period of the page at the provided URL.
-pub fn get_issue_urls_in_period(url: &str, options: &ScraperOptions) -> io::Result<Vec<String>> {
+pub fn get_issue_urls_in_period(url: &str, options: &ScraperOptions) -> Result<Vec<String>> {
+    Ok(get_issues_in_period(url, options)?.into_iter().map(|x| x.url).collect())
+}
+
+/// Gets the issues within the selected period of the page at the provided URL, without fetching their metadata.
+pub fn get_issues_in_period(url: &str, options: &ScraperOptions) -> Result<Vec<IssueListing>> {
     let mut ret = Vec::new();
 
-    let res = try_download(url, options.download_attempts)?;
-    let body = res.text().to_result()?;
+    let res = try_download(url, options)?;
+    let body = res.text();
     let doc = Html::parse_document(&body);
 
-    let selector = Selector::parse("div.allissues_gallerycell a:first-child").to_result()?;
-    for element in doc.select(&selector) {
-        if let Some(x) = element.attr("href") {
-            ret.push(x.to_string());
+    let link_selector = selector("a")?;
+    let image_selector = selector("img")?;
+    for cell in doc.select(&selector("div.allissues_gallerycell")?) {
+        let Some(href) = cell.select(&link_selector).next().and_then(|x| x.attr("href")) else {
+            continue;
+        };
+        let issue_url = resolve_url(url, href)?;
+
+        // Caption is the text below the cover, falling back to the cover's alternate text.
+        let mut caption = cell.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ");
+        if caption.is_empty() {
+            caption = cell
+                .select(&image_selector)
+                .find_map(|x| x.attr("alt"))
+                .unwrap_or_default()
+                .trim()
+                .to_string();
         }
+
+        ret.push(IssueListing {
+            id: id_from_url(&issue_url)?,
+            url: issue_url,
+            caption,
+            period_url: url.to_string(),
+            metadata: None,
+        });
     }
 
     Ok(ret)
 }
+
+/// Lists all issues within the selected period of the page at the provided URL, without fetching their metadata.
+pub fn list_period(url: &str, options: &ScraperOptions) -> Result<Vec<IssueListing>> {
+    let url = sanitize_url(url, Some(options))?;
+
+    if options.verbose {
+        println!("Attempting download of period page with url: {url}");
+    }
+
+    get_issues_in_period(&url, options)
+}
+
+/// Lists all issues within the series of the issue at the provided URL, in the order of their periods, without
+/// fetching their metadata.
+pub fn list_all(url: &str, options: &ScraperOptions) -> Result<Vec<IssueListing>> {
+    let url = sanitize_url(url, Some(options))?;
+
+    if options.verbose {
+        println!("Attempting download of base page with url: {url}");
+    }
+
+    // Issues can appear in more than one period, so only keep the first listing of each.
+    let mut seen = HashSet::new();
+    let mut ret = Vec::new();
+    for period_url in get_period_urls(&url, options)? {
+        if options.verbose {
+            println!("Attempting download of period page with url: {period_url}");
+        }
+        for issue in get_issues_in_period(&period_url, options)? {
+            if seen.insert(issue.id.clone()) {
+                ret.push(issue);
+            }
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+    use crate::scraper::transport::Response;
+    use std::sync::{Arc, Mutex};
+
+    const ABOUT_URL: &str = "https://books.google.us/books?id=CFEEAAAAMBAJ&hl=en";
+    const PERIOD_URL: &str = "https://books.google.com/books?id=CFEEAAAAMBAJ&atm_aiy=1960&hl=en";
+
+    #[test]
+    fn period_discovery() {
+        let options = ScraperOptions {
+            transport: fixtures::transport(&[(ABOUT_URL, "magazine_about.html")]),
+            ..Default::default()
+        };
+        let periods = get_period_urls(ABOUT_URL, &options).unwrap();
+        assert_eq!(
+            periods,
+            [
+                "https://books.google.com/books?id=CFEEAAAAMBAJ&atm_aiy=1930&hl=en",
+                "https://books.google.com/books?id=CFEEAAAAMBAJ&atm_aiy=1940&hl=en",
+                ABOUT_URL,
+                "https://books.google.us/books?id=CFEEAAAAMBAJ&atm_aiy=1970&hl=en",
+            ]
+        );
+
+        // Pages without a period selector are treated as a single period.
+        let options = ScraperOptions {
+            transport: fixtures::transport(&[(ABOUT_URL, "newspaper_about.html")]),
+            ..Default::default()
+        };
+        assert_eq!(get_period_urls(ABOUT_URL, &options).unwrap(), [ABOUT_URL]);
+    }
+
+    #[test]
+    fn issue_discovery() {
+        let options = ScraperOptions {
+            transport: fixtures::transport(&[(PERIOD_URL, "magazine_period.html")]),
+            ..Default::default()
+        };
+        let issues = get_issue_urls_in_period(PERIOD_URL, &options).unwrap();
+        assert_eq!(
+            issues,
+            [
+                "https://books.google.com/books?id=pEEEAAAAMBAJ&lpg=PP1&pg=PP1",
+                "https://books.google.com/books?id=CFEEAAAAMBAJ&lpg=PP1&pg=PP1",
+                "https://books.google.com/books?id=yEEEAAAAMBAJ&lpg=PP1&pg=PP1",
+            ]
+        );
+        let ids: Vec<_> = issues.iter().map(|x| id_from_url(x).unwrap()).collect();
+        assert_eq!(ids, ["pEEEAAAAMBAJ", "CFEEAAAAMBAJ", "yEEEAAAAMBAJ"]);
+    }
+
+    #[test]
+    fn issue_listing_across_periods() {
+        let period_url = |tld: &str, year: u32| {
+            std::format!("https://books.google{tld}/books?id=CFEEAAAAMBAJ&atm_aiy={year}&hl=en")
+        };
+        let (p1930, p1940, p1970) = (period_url(".com", 1930), period_url(".com", 1940), period_url(".us", 1970));
+        let options = ScraperOptions {
+            transport: fixtures::transport(&[
+                (ABOUT_URL, "magazine_about.html"),
+                (&p1930, "magazine_period.html"),
+                (&p1940, "magazine_period.html"),
+                (&p1970, "magazine_about.html"),
+            ]),
+            ..Default::default()
+        };
+
+        // Issues listed again in later periods are only included once, with their captions.
+        let issues = list_all(ABOUT_URL, &options).unwrap();
+        let listed: Vec<_> = issues.iter().map(|x| (x.id.as_str(), x.caption.as_str())).collect();
+        assert_eq!(
+            listed,
+            [
+                ("pEEEAAAAMBAJ", "Jan 3, 1969"),
+                ("CFEEAAAAMBAJ", "Oct 3, 1969"),
+                ("yEEEAAAAMBAJ", "Dec 26, 1969"),
+            ]
+        );
+        assert!(issues.iter().all(|x| x.period_url == p1930 && x.metadata.is_none()));
+    }
+
+    #[test]
+    fn period_download_skips_captions_out_of_range() {
+        const SANITIZED_PERIOD_URL: &str = "https://books.google.com/books?id=CFEEAAAAMBAJ&hl=en&atm_aiy=1960";
+        let inner = fixtures::transport(&[(SANITIZED_PERIOD_URL, "magazine_period.html")]);
+        let requested = Arc::new(Mutex::new(Vec::<String>::new()));
+        let log = requested.clone();
+        let transport = move |url: &str| -> std::io::Result<Response> {
+            log.lock().unwrap().push(url.to_string());
+            inner.get(url)
+        };
+        let options = ScraperOptions {
+            download_attempts: 1,
+            tld: ".com".to_string(),
+            filter: IssueFilter {
+                after: PublishDate::parse_iso("1969-06"),
+                ..Default::default()
+            },
+            transport: Arc::new(transport),
+            ..Default::default()
+        };
+
+        // Issues that can't be fetched are reported and skipped, but the January issue is never requested.
+        download_period(PERIOD_URL, ".", &options, &mut HashSet::new()).unwrap();
+        let requested = requested.lock().unwrap();
+        assert!(requested.iter().any(|x| x.contains("CFEEAAAAMBAJ") && x != SANITIZED_PERIOD_URL));
+        assert!(requested.iter().any(|x| x.contains("yEEEAAAAMBAJ")));
+        assert!(!requested.iter().any(|x| x.contains("pEEEAAAAMBAJ")));
+    }
+}
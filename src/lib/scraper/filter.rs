@@ -0,0 +1,97 @@
+use super::types::{BookMetadata, PublishDate};
+
+/// Filters selecting which issues are downloaded, mainly for use with period and full download modes.
+///
+/// Dates are compared with as much precision as both dates have, so an issue dated "Sep 1955" is included by
+/// both `after` 1955-09-30 and `before` 1955-09-01, while an issue with no known year is included by no date range.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IssueFilter {
+    /// Only include issues published on or after this date.
+    pub after: Option<PublishDate>,
+    /// Only include issues published on or before this date.
+    pub before: Option<PublishDate>,
+    /// Only include issues whose full title contains this text, ignoring case.
+    pub title: Option<String>,
+    /// Maximum number of issues to download in period and full download modes, not counting those skipped.
+    pub limit: Option<usize>,
+}
+
+impl IssueFilter {
+    /// Returns true if no filters are set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Checks a publish date against the date range, e.g. one parsed from an issue gallery caption.
+    ///
+    /// Returns `None` if a date range is set but the date has no year, so it can't be checked.
+    pub fn matches_date(&self, date: &PublishDate) -> Option<bool> {
+        if self.after.is_none() && self.before.is_none() {
+            return Some(true);
+        }
+        let (earliest, latest) = (date.earliest()?, date.latest()?);
+        let after = self.after.and_then(|x| x.earliest()).is_none_or(|x| latest >= x);
+        let before = self.before.and_then(|x| x.latest()).is_none_or(|x| earliest <= x);
+        Some(after && before)
+    }
+
+    /// Checks the metadata of an issue against the date range and title filter.
+    pub fn matches(&self, meta: &BookMetadata) -> bool {
+        let date = self.matches_date(&meta.parsed_publish_date()).unwrap_or(false);
+        let title = self
+            .title
+            .as_ref()
+            .is_none_or(|x| meta.get_full_title().to_lowercase().contains(&x.to_lowercase()));
+        date && title
+    }
+
+    /// Returns true if the given number of issues reaches the limit.
+    pub fn limit_reached(&self, downloaded: usize) -> bool {
+        self.limit.is_some_and(|x| downloaded >= x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::types::ContentType;
+
+    #[test]
+    fn date_range() {
+        let filter = IssueFilter {
+            after: PublishDate::parse_iso("1950-01-01"),
+            before: PublishDate::parse_iso("1959"),
+            ..Default::default()
+        };
+        let matches = |text: &str| filter.matches_date(&PublishDate::parse(text));
+        assert_eq!(matches("Jan 1, 1950"), Some(true));
+        assert_eq!(matches("Dec 31, 1959"), Some(true));
+        assert_eq!(matches("Sep 1955"), Some(true));
+        assert_eq!(matches("Dec 31, 1949"), Some(false));
+        assert_eq!(matches("Jan 1, 1960"), Some(false));
+        assert_eq!(matches("Winter issue"), None);
+        assert_eq!(IssueFilter::default().matches_date(&PublishDate::parse("")), Some(true));
+    }
+
+    #[test]
+    fn title_and_limit() {
+        let filter = IssueFilter {
+            title: Some("oct".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let meta = BookMetadata {
+            title: String::from("LIFE"),
+            publish_date: String::from("Oct 3, 1969"),
+            book_type: ContentType::Magazine,
+            ..Default::default()
+        };
+        assert!(filter.matches(&meta));
+        assert!(!filter.matches(&BookMetadata {
+            publish_date: String::from("Nov 7, 1969"),
+            ..meta
+        }));
+        assert!(!filter.limit_reached(1));
+        assert!(filter.limit_reached(2));
+    }
+}
@@ -0,0 +1,81 @@
+//! Saved Google Books pages and API responses for testing without network access.
+
+use std::collections::HashMap;
+use std::io::{self};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use scraper::Html;
+
+use super::transport::{Response, Transport};
+
+/// Reads the contents of a file in the fixture directory.
+pub(crate) fn read(name: &str) -> String {
+    let path = std::format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("failed to read fixture {path}: {e}"))
+}
+
+/// Parses an HTML file in the fixture directory.
+pub(crate) fn document(name: &str) -> Html {
+    Html::parse_document(&read(name))
+}
+
+/// Creates a transport that serves fixture files for the given URLs and responds 404 to anything else.
+///
+/// # Arguments
+///
+/// * `routes` - Pairs of URLs and the names of the fixture files to serve for them.
+pub(crate) fn transport(routes: &[(&str, &str)]) -> Arc<dyn Transport> {
+    let routes: HashMap<String, String> = routes
+        .iter()
+        .map(|(url, name)| (url.to_string(), read(name)))
+        .collect();
+    Arc::new(move |url: &str| -> io::Result<Response> {
+        Ok(match routes.get(url) {
+            Some(body) => Response::new(200, body.as_str()),
+            None => Response::new(404, "Not Found"),
+        })
+    })
+}
+
+/// Empty directory for a test to write files to, removed when dropped, even if the test panics.
+///
+/// Names include the process ID and a counter, so tests running at once never share a directory.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a directory named after the test using it.
+    pub(crate) fn new(name: &str) -> TempDir {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(std::format!(
+            "gbscraper-{name}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap_or_else(|e| panic!("failed to create {}: {e}", path.display()));
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.0);
+    }
+}
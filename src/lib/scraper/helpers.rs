@@ -1,35 +1,64 @@
-use std::fmt::Display;
-use std::io::{self};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use scraper::Selector;
 use url::Url;
 
+use crate::error::{Error, Result};
 use crate::scraper::FALLBACK_TLD;
+use crate::scraper::transport::Response;
 use crate::scraper::types::ScraperOptions;
 
 /// Parse book ID from URL.
 /// 
 /// # Arguments
 /// * `url` - URL of book or issue to download.
-pub(crate) fn id_from_url(url: &str) -> io::Result<String> {
+pub(crate) fn id_from_url(url: &str) -> Result<String> {
     // Note: old style URL: https://books.google.com/books?id=$book_id&$other_args...
     //       new style URL: https://www.google.com/books/edition/$arbitrary_title/$book_id?$args...
 
-    let url_obj = Url::try_from(url).to_result()?;
-    const INVALID_URL: &str = "Invalid URL";
+    let url_obj = parse_url(url)?;
     Ok(match url_obj.query_pairs().find(|x| x.0 == "id") {
         Some(x) => x.1.to_string(),
         None => url_obj
             .path_segments()
-            .to_result(INVALID_URL)?
-            .next_back()
-            .to_result(INVALID_URL)?
+            .and_then(|mut x| x.next_back())
+            .ok_or_else(|| Error::InvalidUrl(url.to_string()))?
             .to_string(),
     })
 }
 
+/// Parses URL, mapping failure to an invalid URL error.
+pub(crate) fn parse_url(url: &str) -> Result<Url> {
+    Url::try_from(url).map_err(|_| Error::InvalidUrl(url.to_string()))
+}
+
+/// Gets the base URL that book pages are requested from.
+pub(crate) fn base_url(options: Option<&ScraperOptions>) -> String {
+    match options.and_then(|x| x.base_url.as_ref()) {
+        Some(x) => x.trim_end_matches('/').to_string(),
+        None => {
+            let tld = options.map_or(FALLBACK_TLD, |x| x.tld.as_str());
+            std::format!("https://books.google{tld}")
+        }
+    }
+}
+
+/// Gets the base URL that newspaper image segments are requested from.
+pub(crate) fn content_base_url(options: Option<&ScraperOptions>) -> String {
+    match options.and_then(|x| x.base_url.as_ref()) {
+        Some(x) => x.trim_end_matches('/').to_string(),
+        None => "https://books.google.com".to_string(),
+    }
+}
+
 /// Generate basic old-style URL from book ID.
 pub(crate) fn url_from_id(id: &str, options: Option<&ScraperOptions>) -> String {
-    let tld = options.map_or(FALLBACK_TLD, |x| x.tld.as_str());
-    std::format!("https://books.google{tld}/books?id={id}&hl=en")
+    std::format!("{}/books?id={id}&hl=en", base_url(options))
+}
+
+/// Gets URL of the plain text view of specified page.
+pub(crate) fn get_text_url(id: &str, page_id: &str, options: Option<&ScraperOptions>) -> String {
+    std::format!("{}&pg={page_id}&output=text", url_from_id(id, options))
 }
 
 /// Gets URL of JSON pertaiing to specified page.
@@ -41,44 +70,45 @@ pub(crate) fn get_json_url(id: &str, first_page: &str, page_id: &str, options: O
 }
 
 /// Converts URL to English and strips unneccessary parameters.
-pub(crate) fn sanitize_url(url: &str, options: Option<&ScraperOptions>) -> io::Result<String> {
+pub(crate) fn sanitize_url(url: &str, options: Option<&ScraperOptions>) -> Result<String> {
     // Strip everything but ID and force English
     let base_url = url_from_id(&id_from_url(url)?, options);
     // Check for period in original URL and add to result if found
     const PERIOD_TAG: &str = "atm_aiy";
-    let url_obj = Url::try_from(url).to_result()?;
+    let url_obj = parse_url(url)?;
     match url_obj.query_pairs().find(|x| x.0 == PERIOD_TAG) {
         Some(x) => Ok(std::format!("{base_url}&{PERIOD_TAG}={}", x.1)),
         None => Ok(base_url),
     }
 }
 
-// Methods to convert between option/result types for error propagation.
-
-pub(crate) trait ToResult<T> {
-    fn to_result(self) -> std::io::Result<T>;
+/// Resolves a link found in a page against the URL of that page.
+pub(crate) fn resolve_url(page_url: &str, href: &str) -> Result<String> {
+    Ok(parse_url(page_url)?
+        .join(href)
+        .map_err(|_| Error::InvalidUrl(href.to_string()))?
+        .to_string())
 }
 
-impl<T, E: Display> ToResult<T> for std::result::Result<T, E> {
-    fn to_result(self) -> std::io::Result<T> {
-        match self {
-            Ok(x) => Ok(x),
-            Err(x) => Err(std::io::Error::other(x.to_string())),
-        }
-    }
+/// Parses CSS selector used to find elements in a page.
+pub(crate) fn selector(selectors: &str) -> Result<Selector> {
+    Selector::parse(selectors)
+        .map_err(|e| Error::MetadataParse(std::format!("invalid selector \"{selectors}\": {e}")))
 }
 
-pub(crate) trait ToResultErrorMessage<T> {
-    fn to_result(self, msg: &str) -> std::io::Result<T>;
+/// Gets the path of a file beside the one at the provided path, named after it with another extension added.
+pub(crate) fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(std::format!(".{extension}"));
+    path.with_file_name(name)
 }
 
-impl<T> ToResultErrorMessage<T> for Option<T> {
-    fn to_result(self, msg: &str) -> std::io::Result<T> {
-        match self {
-            Some(x) => Ok(x),
-            None => Err(std::io::Error::other(msg.to_string())),
-        }
-    }
+/// Replaces the contents of a file by writing them to a temporary file beside it and renaming that, so an
+/// interruption never leaves a truncated file behind.
+pub(crate) fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let tmp_path = sibling_path(path, "tmp");
+    std::fs::write(&tmp_path, contents)?;
+    Ok(std::fs::rename(tmp_path, path)?)
 }
 
 /// Generate filename for image.
@@ -87,43 +117,133 @@ pub(crate) fn generate_image_filename(page_number: &usize, page_id: &str, ext: &
 }
 
 /// Determine image extension by the content header.
-pub(crate) fn get_image_ext(res: &reqwest::blocking::Response) -> io::Result<String> {
+pub(crate) fn get_image_ext(res: &Response) -> String {
     let mut ext = "jpg";
-    for (name, value) in res.headers() {
-        if name.as_str() == "content-type" {
-            ext = value.to_str().to_result()?;
-            let mut start = 0;
-            if let Some(x) = ext.find("/") {
-                start = x + 1
-            }
-            ext = &ext[start..];
-            if ext == "jpeg" {
-                ext = "jpg"
+    if let Some(value) = res.header("content-type") {
+        ext = value;
+        let mut start = 0;
+        if let Some(x) = ext.find("/") {
+            start = x + 1
+        }
+        ext = &ext[start..];
+        if ext == "jpeg" {
+            ext = "jpg"
+        }
+    }
+    ext.to_string()
+}
+
+/// Text found in Google's CAPTCHA and "unusual traffic" interstitials.
+const CAPTCHA_MARKERS: [&str; 4] = [
+    "detected unusual traffic",
+    "google.com/sorry/",
+    "g-recaptcha",
+    "captcha-form",
+];
+
+/// Parses the value of a Retry-After header, which may be a number of seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = time::OffsetDateTime::parse(value, &time::format_description::well_known::Rfc2822).ok()?;
+    let remaining = date - time::OffsetDateTime::now_utc();
+    Some(Duration::try_from(remaining).unwrap_or(Duration::ZERO))
+}
+
+/// Classifies response as a failure if it doesn't contain the requested content.
+fn check_response(url: &str, res: Response, expect_image: bool) -> Result<Response> {
+    let url = url.to_string();
+    let retry_after = res.header("retry-after").and_then(parse_retry_after);
+    match res.status {
+        200..=299 => (),
+        429 => {
+            return Err(Error::RateLimited {
+                url,
+                retry_after,
+                captcha: false,
+            })
+        }
+        status => {
+            return Err(Error::HttpStatus {
+                url,
+                status,
+                retry_after,
+            })
+        }
+    }
+
+    let content_type = res.header("content-type").unwrap_or_default().to_lowercase();
+    if !content_type.starts_with("image/") {
+        if content_type.is_empty() || content_type.contains("html") {
+            let body = String::from_utf8_lossy(&res.body);
+            if CAPTCHA_MARKERS.iter().any(|x| body.contains(x)) {
+                return Err(Error::RateLimited {
+                    url,
+                    retry_after: None,
+                    captcha: true,
+                });
             }
-            break;
+        }
+        if expect_image {
+            return Err(Error::UnexpectedContent { url, content_type });
         }
     }
-    Ok(ext.to_string())
+    Ok(res)
 }
 
-/// Determine image extension by the content header.
-pub(crate) fn try_download(url: &str, mut attempts: u32) -> io::Result<reqwest::blocking::Response> {
+/// Download file at URL using the transport and rate limiter in the provided options, retrying on failure.
+pub(crate) fn try_download(url: &str, options: &ScraperOptions) -> Result<Response> {
+    try_download_internal(url, options, false)
+}
+
+/// Download image at URL, treating responses with any other content type as failures.
+pub(crate) fn try_download_image(url: &str, options: &ScraperOptions) -> Result<Response> {
+    try_download_internal(url, options, true)
+}
+
+/// Longest time to wait between retries when backing off.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+fn try_download_internal(url: &str, options: &ScraperOptions, expect_image: bool) -> Result<Response> {
+    let mut attempts = options.download_attempts;
     let indefinite = attempts == 0;
-    let mut res: io::Result<reqwest::blocking::Response> = Err(io::Error::other(""));
-    while indefinite || attempts > 0 {
-        res = reqwest::blocking::get(url).to_result();
-        if let Ok(res) = res {
-            return Ok(res);
+    let mut backoff = options.retry_backoff;
+    loop {
+        options.rate_limiter.wait();
+        let err = match options.transport.get(url) {
+            Ok(res) => match check_response(url, res, expect_image) {
+                Ok(res) => return Ok(res),
+                Err(e) => e,
+            },
+            Err(source) => Error::Network {
+                url: url.to_string(),
+                source,
+            },
+        };
+
+        // Errors such as client errors and unexpected content won't be fixed by trying again.
+        if !err.is_retryable() {
+            return Err(err);
         }
         if !indefinite {
             attempts -= 1;
-            eprintln!("Download failed for {url}. {attempts} attempt(s) remaining...");
+            if attempts == 0 {
+                return Err(err);
+            }
+            eprintln!("Download failed for {url}: {err}. {attempts} attempt(s) remaining...");
+        } else {
+            eprintln!("Download failed for {url}: {err}. Retrying...");
         }
-        else{
-            eprintln!("Download failed for {url}. Retrying...");
+
+        // Wait as long as server asked, otherwise back off exponentially.
+        let wait = err.retry_after().unwrap_or(backoff).min(MAX_BACKOFF);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
         }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
     }
-    res
 }
 
 #[cfg(test)]
@@ -132,6 +252,7 @@ mod tests {
 
     const ID: &str = "FAKE_ID";
     const ARGS: &str = "a=aa&b=bb&c=1";
+    const URL: &str = "http://test";
 
     #[test]
     fn old_url_parsing() {
@@ -162,5 +283,93 @@ mod tests {
         let url = url_from_id(ID, Some(&options));
         let expected = std::format!("https://books.google{tld}/books?id={ID}&hl=en");
         assert_eq!(url, expected);
+
+        // Use base URL from options in place of Google when provided.
+        let options = ScraperOptions {
+            base_url: Some("http://localhost:8080/".to_string()),
+            ..Default::default()
+        };
+        let url = url_from_id(ID, Some(&options));
+        let expected = std::format!("http://localhost:8080/books?id={ID}&hl=en");
+        assert_eq!(url, expected);
+    }
+
+    #[test]
+    fn retry_after_parsing() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn response_classification() {
+        let ok = |res: Response, expect_image| check_response(URL, res, expect_image).is_ok();
+        assert!(ok(Response::new(200, "{}"), false));
+        assert!(ok(Response::new(200, "").with_header("Content-Type", "image/jpeg"), true));
+        assert!(!ok(Response::new(200, "{}").with_header("Content-Type", "application/json"), true));
+        assert!(matches!(
+            check_response(URL, Response::new(429, "").with_header("Retry-After", "5"), false),
+            Err(Error::RateLimited { retry_after: Some(x), captcha: false, .. }) if x == Duration::from_secs(5)
+        ));
+        assert!(matches!(
+            check_response(
+                URL,
+                Response::new(200, "<p>Our systems have detected unusual traffic from your computer network.</p>")
+                    .with_header("Content-Type", "text/html; charset=UTF-8"),
+                true
+            ),
+            Err(Error::RateLimited { captcha: true, .. })
+        ));
+    }
+
+    #[test]
+    fn retries_by_status() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // Serves the given statuses in order, then succeeds.
+        let options_with_statuses = |statuses: Vec<u16>| {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let counter = calls.clone();
+            let transport = move |_: &str| -> std::io::Result<Response> {
+                let i = counter.fetch_add(1, Ordering::SeqCst);
+                Ok(Response::new(*statuses.get(i).unwrap_or(&200), "ok"))
+            };
+            let options = ScraperOptions {
+                download_attempts: 3,
+                retry_backoff: Duration::ZERO,
+                transport: Arc::new(transport),
+                ..Default::default()
+            };
+            (options, calls)
+        };
+
+        // Server errors and rate limits are retried.
+        let (options, calls) = options_with_statuses(vec![503, 429]);
+        assert_eq!(try_download(URL, &options).unwrap().text(), "ok");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Until attempts run out.
+        let (options, calls) = options_with_statuses(vec![500, 500, 500]);
+        assert!(try_download(URL, &options).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Client errors fail immediately.
+        let (options, calls) = options_with_statuses(vec![404]);
+        let err = try_download(URL, &options).unwrap_err();
+        assert!(matches!(err, Error::HttpStatus { status: 404, .. }));
+        assert!(!err.is_retryable());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // As do redirects that weren't followed and other unexpected statuses.
+        let (options, calls) = options_with_statuses(vec![304]);
+        assert!(!try_download(URL, &options).unwrap_err().is_retryable());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // And pages consistently served in place of images.
+        let (options, calls) = options_with_statuses(vec![]);
+        let err = try_download_image(URL, &options).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedContent { .. }));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
     }
 }
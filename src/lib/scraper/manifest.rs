@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{self};
+use std::path::Path;
+
+use super::helpers::write_atomic;
+use super::types::BookMetadata;
+use crate::error::Result;
+use crate::writer::pdf::{LinkTarget, PageLink, PageLinks, TableOfContents};
+
+/// Record of the pages downloaded for a book, kept in its image directory so interrupted downloads can be resumed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// ID of book.
+    pub id: String,
+    /// True once every available page has been downloaded.
+    pub complete: bool,
+    /// Pages downloaded so far, ordered by page number once complete.
+    pub pages: Vec<ManifestPage>,
+    /// Table of contents entries for the book, in their original order.
+    pub toc: Vec<ManifestTocEntry>,
+    /// Metadata of the book, so it can be converted again later without being fetched.
+    #[serde(default)]
+    pub metadata: Option<BookMetadata>,
+}
+
+/// Page that has been downloaded to disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestPage {
+    /// Absolute page number used for ordering.
+    pub page_number: usize,
+    /// Google's ID for the page.
+    pub page_id: String,
+    /// Filename of image within the image directory.
+    pub filename: String,
+    /// SHA-256 of image file, as lowercase hex.
+    pub sha256: String,
+    /// Clickable regions of page.
+    #[serde(default)]
+    pub links: Vec<ManifestLink>,
+}
+
+/// Clickable region of a page, in pixels of the downloaded image from its top left corner.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestLink {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Google's ID for the page linked to, if an internal link.
+    pub page_id: Option<String>,
+    /// URL linked to, if an external link.
+    pub url: Option<String>,
+}
+
+/// Table of contents entry pointing to a page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestTocEntry {
+    /// Google's ID for the page.
+    pub page_id: String,
+    /// Title of entry.
+    pub title: String,
+    /// Nesting level of entry, starting at 0 for top level.
+    #[serde(default)]
+    pub level: usize,
+}
+
+impl Manifest {
+    /// Name of manifest file within image directory.
+    pub const FILENAME: &'static str = "manifest.json";
+
+    /// Creates an empty manifest for a book.
+    pub fn new(id: &str) -> Manifest {
+        Manifest {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Loads manifest from image directory, if one exists.
+    pub fn load(image_dir: &str) -> Result<Option<Manifest>> {
+        let path = Path::new(image_dir).join(Self::FILENAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&text)?))
+    }
+
+    /// Builds a manifest for the images in a directory that has none, e.g. one kept by an older version.
+    ///
+    /// Page IDs are taken from image filenames in the form `00001-PA1.png`, falling back to the filename
+    /// without its extension.
+    pub fn from_image_dir(id: &str, image_dir: &str) -> Result<Manifest> {
+        let mut manifest = Manifest::new(id);
+        for (i, entry) in crate::writer::read_image_dir(image_dir)?.iter().enumerate() {
+            let path = entry.path();
+            let stem = path.file_stem().map(|x| x.to_string_lossy()).unwrap_or_default();
+            let page_id = stem.split_once('-').map_or(&*stem, |(_, x)| x);
+            manifest.pages.push(ManifestPage {
+                page_number: i + 1,
+                page_id: page_id.to_string(),
+                filename: entry.file_name().to_string_lossy().to_string(),
+                sha256: file_sha256(&path)?,
+                links: Vec::new(),
+            });
+        }
+        manifest.complete = true;
+        Ok(manifest)
+    }
+
+    /// Saves manifest to image directory, replacing any previous version.
+    pub fn save(&self, image_dir: &str) -> Result<()> {
+        write_atomic(&Path::new(image_dir).join(Self::FILENAME), serde_json::to_string_pretty(self)?)
+    }
+
+    /// Removes pages whose image is missing or doesn't match its checksum, returning the number removed.
+    pub fn discard_invalid_pages(&mut self, image_dir: &str) -> usize {
+        let before = self.pages.len();
+        self.pages.retain(|page| {
+            let path = Path::new(image_dir).join(&page.filename);
+            file_sha256(&path).is_ok_and(|x| x == page.sha256)
+        });
+        before - self.pages.len()
+    }
+
+    /// Replaces table of contents entries with those provided, keeping the existing ones if none are.
+    pub fn update_toc(&mut self, toc: &[ManifestTocEntry]) {
+        if toc.is_empty() {
+            return;
+        }
+        self.toc = toc.to_vec();
+    }
+
+    /// Builds table of contents mapping the filenames of downloaded pages to their titles.
+    pub fn table_of_contents(&self) -> TableOfContents {
+        let mut toc = TableOfContents::new();
+        for entry in &self.toc {
+            if let Some(page) = self.pages.iter().find(|x| x.page_id == entry.page_id) {
+                toc.add_page_at_level(&entry.title, &page.filename, entry.level);
+            }
+        }
+        toc
+    }
+
+    /// Builds lookup of links on downloaded pages, dropping any that link to pages that weren't downloaded.
+    pub fn page_links(&self) -> PageLinks {
+        let filenames: HashMap<&str, &str> = self
+            .pages
+            .iter()
+            .map(|x| (x.page_id.as_str(), x.filename.as_str()))
+            .collect();
+        let mut links = PageLinks::new();
+        for page in &self.pages {
+            for link in &page.links {
+                let target = match (&link.page_id, &link.url) {
+                    (Some(page_id), _) => match filenames.get(page_id.as_str()) {
+                        Some(filename) => LinkTarget::Page(filename.to_string()),
+                        None => continue,
+                    },
+                    (None, Some(url)) => LinkTarget::Uri(url.clone()),
+                    (None, None) => continue,
+                };
+                links.add_link(
+                    &page.filename,
+                    PageLink {
+                        rect: [link.x, link.y, link.width, link.height],
+                        target,
+                    },
+                );
+            }
+        }
+        links
+    }
+}
+
+/// Computes the SHA-256 of a file as lowercase hex.
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let digest = Sha256::digest(std::fs::read(path)?);
+    Ok(digest.iter().map(|x| std::format!("{x:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+
+    #[test]
+    fn resume_from_saved_manifest() {
+        let dir = fixtures::TempDir::new("manifest");
+        let dir_str = dir.to_str().unwrap();
+
+        let mut manifest = Manifest::new("FAKE_ID");
+        for (i, contents) in ["one", "two", "three"].iter().enumerate() {
+            let filename = std::format!("{i}.jpg");
+            std::fs::write(dir.join(&filename), contents).unwrap();
+            manifest.pages.push(ManifestPage {
+                page_number: i + 1,
+                page_id: std::format!("PA{}", i + 1),
+                filename: filename.clone(),
+                sha256: file_sha256(&dir.join(&filename)).unwrap(),
+                links: Vec::new(),
+            });
+        }
+        manifest.update_toc(&[ManifestTocEntry {
+            page_id: "PA2".to_string(),
+            title: "Chapter 2".to_string(),
+            level: 0,
+        }]);
+        manifest.save(dir_str).unwrap();
+
+        // Corrupt one page and remove another.
+        std::fs::write(dir.join("0.jpg"), "on").unwrap();
+        std::fs::remove_file(dir.join("2.jpg")).unwrap();
+
+        let mut manifest = Manifest::load(dir_str).unwrap().unwrap();
+        assert_eq!(manifest.discard_invalid_pages(dir_str), 2);
+        assert_eq!(manifest.pages.len(), 1);
+        assert_eq!(manifest.pages[0].page_id, "PA2");
+
+        // TOC survives reload and is kept if no new entries are parsed.
+        manifest.update_toc(&[]);
+        assert_eq!(manifest.toc.len(), 1);
+        assert!(!manifest.table_of_contents().is_empty());
+    }
+
+    #[test]
+    fn manifest_from_image_filenames() {
+        let dir = fixtures::TempDir::new("manifest-from-images");
+        for filename in ["00001-PP1.png", "00002-PA1.jpg", "cover.png", "notes.txt"] {
+            std::fs::write(dir.join(filename), filename).unwrap();
+        }
+
+        let mut manifest = Manifest::from_image_dir("FAKE_ID", dir.to_str().unwrap()).unwrap();
+        let page_ids: Vec<_> = manifest.pages.iter().map(|x| x.page_id.as_str()).collect();
+        assert_eq!(page_ids, ["PP1", "PA1", "cover"]);
+        assert_eq!(manifest.discard_invalid_pages(dir.to_str().unwrap()), 0);
+
+        manifest.update_toc(&[ManifestTocEntry {
+            page_id: "PA1".to_string(),
+            title: "Chapter 1".to_string(),
+            level: 0,
+        }]);
+        assert_eq!(manifest.table_of_contents().page_title("00002-PA1.jpg"), Some("Chapter 1"));
+    }
+}
@@ -1,9 +1,26 @@
+pub mod archive;
 pub mod batching;
-mod helpers;
+pub mod filter;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub(crate) mod helpers;
+pub mod manifest;
+pub mod naming;
+pub mod rate_limit;
 #[allow(clippy::module_inception)]
 pub mod scraper;
+pub mod sync;
+pub mod toc;
+pub mod transport;
 pub mod types;
 
+pub use archive::*;
 pub use batching::*;
+pub use filter::*;
+pub use manifest::*;
+pub use naming::*;
+pub use rate_limit::*;
 pub use scraper::*;
+pub use sync::*;
+pub use transport::*;
 pub use types::*;
@@ -0,0 +1,295 @@
+use sanitise_file_name::sanitise_with_options;
+use std::str::FromStr;
+
+use super::types::{BookMetadata, ContentType};
+use crate::error::{Error, Result};
+
+/// Maximum length in bytes of any component of an output path.
+const MAX_COMPONENT_LENGTH: usize = 255;
+/// Bytes reserved at the end of the final path component for a file extension such as ".pdf".
+const RESERVED_EXTENSION_LENGTH: usize = 8;
+
+/// Template for the path, relative to the target directory, that a book is saved to.
+///
+/// Fields are written in braces, e.g. `{title}`, and `/` separates directories. Literal braces are
+/// written as `{{` and `}}`. The image directory and each output file share the final component,
+/// with the file extension appended for the latter.
+///
+/// Available fields are `title`, `full_title`, `publish_date`, `year`, `month`, `day`, `volume`,
+/// `issn`, `author`, `publisher` and `id`. Fields with no value for a book are left empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    components: Vec<Vec<Segment>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Title,
+    FullTitle,
+    PublishDate,
+    Year,
+    Month,
+    Day,
+    Volume,
+    Issn,
+    Author,
+    Publisher,
+    Id,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        Some(match name {
+            "title" => Field::Title,
+            "full_title" => Field::FullTitle,
+            "publish_date" => Field::PublishDate,
+            "year" => Field::Year,
+            "month" => Field::Month,
+            "day" => Field::Day,
+            "volume" => Field::Volume,
+            "issn" => Field::Issn,
+            "author" => Field::Author,
+            "publisher" => Field::Publisher,
+            "id" => Field::Id,
+            _ => return None,
+        })
+    }
+
+    fn value(&self, meta: &BookMetadata) -> String {
+        let date = meta.parsed_publish_date();
+        match self {
+            Field::Title => meta.title.clone(),
+            Field::FullTitle => meta.get_full_title(),
+            Field::PublishDate => meta.publish_date.clone(),
+            Field::Year => date.year.map(|x| x.to_string()).unwrap_or_default(),
+            Field::Month => date.month.map(|x| std::format!("{x:02}")).unwrap_or_default(),
+            Field::Day => date.day.map(|x| std::format!("{x:02}")).unwrap_or_default(),
+            Field::Volume => meta.volume.clone(),
+            Field::Issn => meta.issn.clone(),
+            Field::Author => meta.author.clone(),
+            Field::Publisher => meta.publisher.clone(),
+            Field::Id => meta.id.clone(),
+        }
+    }
+}
+
+impl NameTemplate {
+    /// Template used for books when none is provided.
+    pub const DEFAULT_BOOK: &'static str = "{full_title} [{id}]";
+    /// Template used for magazine and newspaper issues when none is provided, grouping issues by publication.
+    pub const DEFAULT_PERIODICAL: &'static str = "{title}/{full_title} [{id}]";
+
+    /// Parses a template, failing if it references an unknown field or has unbalanced braces.
+    pub fn parse(template: &str) -> Result<NameTemplate> {
+        let invalid = |msg: &str| Error::InvalidTemplate(std::format!("{msg} in \"{template}\""));
+        let mut components = Vec::new();
+        for component in template.split('/') {
+            let mut segments = Vec::new();
+            let mut literal = String::new();
+            let mut chars = component.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '{' if chars.peek() == Some(&'{') => {
+                        chars.next();
+                        literal.push('{');
+                    }
+                    '}' if chars.peek() == Some(&'}') => {
+                        chars.next();
+                        literal.push('}');
+                    }
+                    '{' => {
+                        let mut name = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('}') => break,
+                                Some(x) => name.push(x),
+                                None => return Err(invalid("Unmatched \"{\"")),
+                            }
+                        }
+                        let field = Field::from_name(name.trim())
+                            .ok_or_else(|| invalid(&std::format!("Unknown field \"{name}\"")))?;
+                        if !literal.is_empty() {
+                            segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                        }
+                        segments.push(Segment::Field(field));
+                    }
+                    '}' => return Err(invalid("Unmatched \"}\"")),
+                    _ => literal.push(c),
+                }
+            }
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(literal));
+            }
+            if !segments.is_empty() {
+                components.push(segments);
+            }
+        }
+        if components.is_empty() {
+            return Err(invalid("Empty path"));
+        }
+        Ok(NameTemplate { components })
+    }
+
+    /// Gets the default template for a type of book.
+    pub fn default_for(book_type: &ContentType) -> NameTemplate {
+        let template = match book_type {
+            ContentType::Magazine | ContentType::Newspaper => Self::DEFAULT_PERIODICAL,
+            ContentType::Book => Self::DEFAULT_BOOK,
+        };
+        Self::parse(template).expect("default template is valid")
+    }
+
+    /// Fills in the template for a book, returning the sanitised relative path without an extension.
+    pub fn render(&self, meta: &BookMetadata) -> String {
+        let last = self.components.len() - 1;
+        self.components
+            .iter()
+            .enumerate()
+            .map(|(i, segments)| {
+                let length_limit = match i == last {
+                    true => MAX_COMPONENT_LENGTH - RESERVED_EXTENSION_LENGTH,
+                    false => MAX_COMPONENT_LENGTH,
+                };
+                Self::render_component(segments, meta, length_limit)
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn render_component(segments: &[Segment], meta: &BookMetadata, length_limit: usize) -> String {
+        let options = sanitise_file_name::Options {
+            length_limit: usize::MAX,
+            extension_cleverness: false,
+            ..Default::default()
+        };
+        let mut parts: Vec<(bool, String)> = segments
+            .iter()
+            .map(|x| match x {
+                Segment::Literal(s) => (false, s.clone()),
+                Segment::Field(f) => match f.value(meta) {
+                    x if x.is_empty() => (true, x),
+                    x => (true, sanitise_with_options(&x, &options)),
+                },
+            })
+            .collect();
+
+        // Shorten the longest field values first so short ones such as the ID survive truncation.
+        loop {
+            let length: usize = parts.iter().map(|(_, s)| s.len()).sum();
+            if length <= length_limit {
+                break;
+            }
+            let Some((_, longest)) = parts
+                .iter_mut()
+                .filter(|(is_field, s)| *is_field && !s.is_empty())
+                .max_by_key(|(_, s)| s.len())
+            else {
+                break;
+            };
+            let mut new_length = longest.len().saturating_sub(length - length_limit);
+            while !longest.is_char_boundary(new_length) {
+                new_length -= 1;
+            }
+            longest.truncate(new_length);
+        }
+
+        let joined: String = parts.into_iter().map(|(_, s)| s).collect();
+        sanitise_with_options(
+            &joined,
+            &sanitise_file_name::Options {
+                length_limit,
+                ..options
+            },
+        )
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn magazine() -> BookMetadata {
+        BookMetadata {
+            id: String::from("N0EEAAAAMBAJ"),
+            title: String::from("LIFE"),
+            publish_date: String::from("Oct 3, 1969"),
+            volume: String::from("Vol. 67, No. 14"),
+            issn: String::from("0024-3019"),
+            publisher: String::from("Time Inc"),
+            book_type: ContentType::Magazine,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_templates() {
+        let meta = magazine();
+        assert_eq!(
+            NameTemplate::default_for(&meta.book_type).render(&meta),
+            "LIFE/LIFE - Oct 3, 1969 [N0EEAAAAMBAJ]"
+        );
+        let book = BookMetadata {
+            book_type: ContentType::Book,
+            ..meta
+        };
+        assert_eq!(
+            NameTemplate::default_for(&book.book_type).render(&book),
+            "LIFE [N0EEAAAAMBAJ]"
+        );
+    }
+
+    #[test]
+    fn custom_template() {
+        let template: NameTemplate = "{publisher}/{title}/{year}-{month}-{day} {{{issn}}} {volume}"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            template.render(&magazine()),
+            "Time Inc/LIFE/1969-10-03 {0024-3019} Vol. 67, No. 14"
+        );
+
+        // Unsafe characters in values can't create extra directories.
+        let meta = BookMetadata {
+            title: String::from("AC/DC: Live"),
+            ..magazine()
+        };
+        let template = NameTemplate::parse("{title}/{id}").unwrap();
+        assert_eq!(template.render(&meta), "AC_DC_ Live/N0EEAAAAMBAJ");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!(matches!(NameTemplate::parse("{name}"), Err(Error::InvalidTemplate(_))));
+        assert!(matches!(NameTemplate::parse("{title"), Err(Error::InvalidTemplate(_))));
+        assert!(matches!(NameTemplate::parse("title}"), Err(Error::InvalidTemplate(_))));
+        assert!(matches!(NameTemplate::parse("//"), Err(Error::InvalidTemplate(_))));
+    }
+
+    #[test]
+    fn truncation_per_component() {
+        let meta = BookMetadata {
+            title: "A".repeat(300),
+            ..magazine()
+        };
+        let template = NameTemplate::parse("{title}/{title} [{id}]").unwrap();
+        let path = template.render(&meta);
+        let components: Vec<_> = path.split('/').collect();
+        assert_eq!(components[0].len(), MAX_COMPONENT_LENGTH);
+        assert_eq!(components[1].len(), MAX_COMPONENT_LENGTH - RESERVED_EXTENSION_LENGTH);
+        assert!(components[1].ends_with(" [N0EEAAAAMBAJ]"));
+    }
+}
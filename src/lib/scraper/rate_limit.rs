@@ -0,0 +1,118 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits how often requests are made, shared between everything using the same [`ScraperOptions`](super::ScraperOptions).
+///
+/// Requests are given consecutive time slots spaced at least `min_interval` apart, plus a random
+/// amount of up to `jitter`, so the limit holds no matter how many threads are downloading at once.
+pub struct RateLimiter {
+    min_interval: Duration,
+    jitter: Duration,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_interval` - Minimum time between the start of consecutive requests.
+    /// * `jitter` - Maximum random time to add to each interval.
+    pub fn new(min_interval: Duration, jitter: Duration) -> RateLimiter {
+        RateLimiter {
+            min_interval,
+            jitter,
+            next_slot: Mutex::new(None),
+        }
+    }
+
+    /// Creates a rate limiter that does not delay requests.
+    pub fn unlimited() -> RateLimiter {
+        Self::new(Duration::ZERO, Duration::ZERO)
+    }
+
+    /// Gets the minimum interval needed to stay under a number of requests per second.
+    pub fn interval_for_rate(requests_per_second: f64) -> Duration {
+        if requests_per_second > 0. {
+            Duration::from_secs_f64(1. / requests_per_second)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Blocks until the next request is allowed to be made.
+    pub fn wait(&self) {
+        if self.min_interval.is_zero() && self.jitter.is_zero() {
+            return;
+        }
+
+        // Reserve a slot, then sleep until it arrives outside of the lock so other threads can queue up behind it.
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_slot.map_or(now, |x| x.max(now));
+            *next_slot = Some(slot + self.min_interval + self.random_jitter());
+            slot
+        };
+        let now = Instant::now();
+        if slot > now {
+            std::thread::sleep(slot - now);
+        }
+    }
+
+    fn random_jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        self.jitter.mul_f64((random as f64) / (u64::MAX as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_from_rate() {
+        assert_eq!(RateLimiter::interval_for_rate(4.), Duration::from_millis(250));
+        assert_eq!(RateLimiter::interval_for_rate(0.), Duration::ZERO);
+    }
+
+    #[test]
+    fn unlimited_does_not_wait() {
+        let limiter = RateLimiter::unlimited();
+        let start = Instant::now();
+        for _ in 0..100 {
+            limiter.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn limit_shared_between_threads() {
+        const INTERVAL: Duration = Duration::from_millis(20);
+        let limiter = RateLimiter::new(INTERVAL, Duration::from_millis(5));
+        let start = Instant::now();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..3 {
+                        limiter.wait();
+                    }
+                });
+            }
+        });
+
+        // First request is immediate and each of the remaining 11 waits at least one interval.
+        assert!(start.elapsed() >= INTERVAL * 11);
+    }
+}
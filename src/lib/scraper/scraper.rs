@@ -1,18 +1,20 @@
 use image::{ColorType, DynamicImage, GenericImage};
-use sanitise_file_name::{sanitise, sanitise_with_options};
-use scraper::selectable::Selectable;
-use scraper::{Html, Selector};
+use scraper::Html;
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::fs::OpenOptions;
-use std::io::{self};
-use std::io::{Read, Write};
-use url::Url;
+use std::sync::Mutex;
 
+use super::archive::*;
 use super::helpers::*;
+use super::manifest::*;
+use super::naming::NameTemplate;
+use super::toc::extract_toc;
 use super::types::*;
 
-use crate::writer::cbz::create_cbz;
-use crate::writer::pdf::{create_pdf_with_toc, TableOfContents};
+use crate::error::{Error, Result};
+use crate::writer::cbz::{create_cbz_with_options, CbzOptions};
+use crate::writer::epub::{create_epub_with_options, EpubOptions};
+use crate::writer::ocr::PageTexts;
+use crate::writer::pdf::{create_pdf_with_options, PdfOptions};
 
 /// Downloads issue at the provided URL and performs any necessary format conversion.
 ///
@@ -25,7 +27,7 @@ pub fn download_issue(
     url: &str,
     dest: &str,
     options: &ScraperOptions,
-) -> io::Result<DownloadStatus> {
+) -> Result<DownloadStatus> {
     download_issue_skip_downloaded(url, dest, options, None)
 }
 
@@ -42,67 +44,83 @@ pub fn download_issue_skip_downloaded(
     dest: &str,
     options: &ScraperOptions,
     already_downloaded: Option<&mut HashSet<String>>,
-) -> io::Result<DownloadStatus> {
+) -> Result<DownloadStatus> {
     // Note: Some books have download links in page: <a class="gbmt goog-menuitem-content" id="" href="$download_url">Download $ebook_format</a>
     //       These links sometimes require captcha, so probably can't be automated.
 
-    // TODO: ensure filename safety
-    // TODO: fix TOC for books without double row indices?
     // TODO: scan for links to already downloadable books
-    // TODO: add file manifests so downloads can be resumed if interrupted
     // TODO: progress bar
-    // TODO: concurrent downloads? (might be a bad idea since google may flag it as unusual behavior)
 
     let id = id_from_url(url)?;
-    let url = url_from_id(&id, Some(options));
+    if already_downloaded.as_ref().is_some_and(|x| x.contains(&id)) {
+        println!("Skipping already downloaded book: {id}...");
+        return Ok(DownloadStatus::Skipped);
+    }
+    let Some(archive) = options.archive_file.as_ref() else {
+        return download_book(&id, dest, options, already_downloaded);
+    };
 
-    if let Some(ref downloaded) = already_downloaded {
-        if downloaded.contains(&id) {
-            println!("Skipping already downloaded book: {id}...");
-            return Ok(DownloadStatus::Skipped);
+    // Claim book and check archive again right before starting, as other processes may share it.
+    let Some(_claim) = Archive::claim(archive, &id)? else {
+        println!("Skipping book being downloaded by another process: {id}...");
+        return Ok(DownloadStatus::Skipped);
+    };
+    if Archive::open(archive)?.contains(&id) {
+        println!("Skipping book downloaded by another process: {id}...");
+        if let Some(downloaded) = already_downloaded {
+            downloaded.insert(id);
         }
+        return Ok(DownloadStatus::Skipped);
     }
 
-    println!("Identifying book: {id}...");
-
-    // Fetch page.
-    if options.verbose {
-        println!("Attempting download of issue page with url: {url}");
+    let result = download_book(&id, dest, options, already_downloaded);
+    if let Err(e) = &result {
+        if let Err(e) = Archive::record_failure(archive, &id, url, dest, e) {
+            eprintln!("Couldn't write to file: {}", e);
+        }
     }
-    let res = try_download(&url, options.download_attempts)?;
-    let body = res.text().to_result()?;
-    let doc = Html::parse_document(&body);
+    result
+}
 
-    // Parse metadata from page.
-    let meta = BookMetadata::from_page(&id, &doc)?;
+/// Downloads book with the provided ID and performs any necessary format conversion.
+fn download_book(
+    id: &str,
+    dest: &str,
+    options: &ScraperOptions,
+    already_downloaded: Option<&mut HashSet<String>>,
+) -> Result<DownloadStatus> {
+    let id = id.to_string();
+
+    println!("Identifying book: {id}...");
+
+    // Fetch page and parse metadata from it.
+    let (doc, meta) = fetch_about_page(&id, options)?;
 
     // Derive paths.
-    let max_length = 255 - meta.id.len() - 7; // truncate title to allow " [id].pdf"
-    let sanitize_options = sanitise_file_name::Options {
-        length_limit: max_length,
-        ..Default::default()
+    let relative_path = match options.name_template.as_ref() {
+        Some(template) => template.render(&meta),
+        None => NameTemplate::default_for(&meta.book_type).render(&meta),
     };
-    let issue_combined_id = std::format!(
-        "{0} [{1}]",
-        sanitise_with_options(&meta.get_full_title(), &sanitize_options),
-        meta.id
-    );
-    let dest = match meta.book_type {
-        ContentType::Magazine | ContentType::Newspaper => {
-            std::format!("{dest}/{0}", sanitise(&meta.title))
-        }
-        ContentType::Book => dest.to_string(),
-    };
-    let issue_pics_dir = std::format!("{dest}/{issue_combined_id}");
-    let filename_pdf = std::format!("{dest}/{issue_combined_id}.pdf");
-    let filename_cbz = std::format!("{dest}/{issue_combined_id}.cbz");
+    let issue_pics_dir = std::format!("{dest}/{relative_path}");
+    let filename_pdf = std::format!("{issue_pics_dir}.pdf");
+    let filename_cbz = std::format!("{issue_pics_dir}.cbz");
+    let filename_epub = std::format!("{issue_pics_dir}.epub");
 
     println!("Found: {}", meta.get_full_title());
 
+    if !options.filter.matches(&meta) {
+        println!("Doesn't match filters. Skipping...");
+        return Ok(DownloadStatus::Skipped);
+    }
+
     // Check if image directory and any needed formats already exist on disk.
 
-    let mut formats = options.formats.clone();
-    let exists_already = std::path::Path::new(&issue_pics_dir).exists();
+    let mut formats = options.formats;
+
+    // A directory holding an incomplete manifest was left by an interrupted download rather than kept deliberately.
+    let mut manifest = Manifest::load(&issue_pics_dir)?.filter(|x| x.id == id);
+    let resuming = manifest.as_ref().is_some_and(|x| !x.complete);
+    let exists_already = std::path::Path::new(&issue_pics_dir).exists() && !resuming;
 
     if std::path::Path::new(&filename_pdf).exists() {
         formats.remove(FormatFlags::Pdf)
@@ -110,55 +128,37 @@ pub fn download_issue_skip_downloaded(
     if std::path::Path::new(&filename_cbz).exists() {
         formats.remove(FormatFlags::Cbz)
     }
+    if std::path::Path::new(&filename_epub).exists() {
+        formats.remove(FormatFlags::Epub)
+    }
 
     if formats == FormatFlags::None && (exists_already || !options.keep_images) {
         println!("Already downloaded. Skipping...");
         return Ok(DownloadStatus::Skipped);
     }
 
-    // Parse TOC info.
-    let mut toc_page_title_lookup: HashMap<String, String> = HashMap::<String, String>::new();
-    let mut parse_msg_logged = false;
-    for element in doc.select(&Selector::parse("div.toc_entry").to_result()?) {
-        if !parse_msg_logged {
-            println!("Parsing table of contents...");
-            parse_msg_logged = true;
-        }
-
-        // Title is the text of the element.
-        let mut bookmark_name = String::new();
-        element.text().for_each(|x| bookmark_name += x);
+    // Fetch JSON to get info about all pages.
+    let res = try_download(&get_json_url(&id, "1", "1", Some(options)), options)?;
+    let issue: IssueJson = serde_json::from_slice(&res.body)?;
 
-        // Page ID is in link URL.
-        if let Some(bookmark_url) = element
-            .select(&Selector::parse("a").to_result()?)
-            .next()
-            .and_then(|x| x.attr("href"))
-        {
-            if let Some(x) = Url::try_from(bookmark_url)
-                .to_result()?
-                .query_pairs()
-                .find(|x| x.0 == "pg")
-            {
-                toc_page_title_lookup.insert(x.1.to_string(), bookmark_name);
+    // Parse TOC info.
+    let toc_entries = match extract_toc(&doc, Some(&issue))? {
+        Some((layout, entries)) => {
+            println!("Parsed table of contents.");
+            if options.verbose {
+                println!("Table of contents layout: {layout:?}");
             }
+            entries
         }
-    }
-
-    // Fetch JSON to get info about all pages.
-    let mut res = try_download(
-        &get_json_url(&id, "1", "1", Some(options)),
-        options.download_attempts,
-    )?;
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
-    let issue: IssueJson = serde_json::from_str(&body).to_result()?;
+        None => Vec::new(),
+    };
 
     // Make lookup of all pages referenced in json and their absolute page number.
     let mut page_number_lookup = HashMap::<String, usize>::new();
     let mut pages_to_download = VecDeque::<String>::new();
     let mut first_page = "1".to_string();
     let mut i_page = 1;
+    let listed_pages: Vec<String> = issue.page.iter().map(|x| x.pid.clone()).collect();
     for page in issue.page {
         if page.src.is_none() {
             page_number_lookup.insert(page.pid.clone(), i_page);
@@ -174,260 +174,566 @@ pub fn download_issue_skip_downloaded(
         return Ok(DownloadStatus::Complete(meta));
     }
 
-    if !exists_already {
+    if !std::path::Path::new(&issue_pics_dir).exists() {
         // Create directory for saving images to.
         std::fs::create_dir_all(&issue_pics_dir)?
     }
 
+    // Resume from pages recorded in manifest that are still intact on disk.
+    let mut manifest = manifest.take().unwrap_or_else(|| Manifest::new(&id));
+    manifest.discard_invalid_pages(&issue_pics_dir);
+    manifest.update_toc(&toc_entries);
+    manifest.metadata = Some(meta.clone());
+    if !manifest.pages.is_empty() {
+        println!("Resuming download with {} page(s) already downloaded...", manifest.pages.len());
+    }
+    manifest.complete = false;
+    manifest.save(&issue_pics_dir)?;
+
     println!("Downloading images...");
 
     // Download all pages and associate filenames in TOC.
-    let mut toc = TableOfContents::new();
-    let mut pages_downloaded = HashSet::<String>::new();
-    while !pages_to_download.is_empty() {
-        // Get next page ID, skip if already downloaded.
-        let page_id = pages_to_download.pop_front().unwrap();
-        if pages_downloaded.contains(&page_id) {
-            continue;
+    let job = PageDownloadJob {
+        id: &id,
+        first_page: &first_page,
+        book_type: &meta.book_type,
+        issue_pics_dir: &issue_pics_dir,
+        page_number_lookup: &page_number_lookup,
+        options,
+    };
+    let mut manifest = job.run(pages_to_download, i_page, manifest)?;
+
+    if manifest.pages.is_empty() {
+        return Err(Error::NoPagesAvailable {
+            id,
+            tld: options.tld.clone(),
+        });
+    }
+
+    manifest.complete = true;
+    manifest.save(&issue_pics_dir)?;
+
+    // Download any formats not already downloaded.
+    convert_image_dir(&issue_pics_dir, &issue_pics_dir, formats, &manifest, Some(&meta))?;
+
+    // Clean up downloaded images unless option is set or directory already existed.
+    if !(options.keep_images || exists_already) {
+        std::fs::remove_dir_all(&issue_pics_dir)?;
+    }
+
+    // All done. Add to list of downloaded books and update archive file if applicable.
+    if let Some(downloaded) = already_downloaded {
+        downloaded.insert(id.to_string());
+    }
+    if let Some(archive) = options.archive_file.as_ref() {
+        let downloaded_pages: HashSet<&str> = manifest.pages.iter().map(|x| x.page_id.as_str()).collect();
+        let pages_missing = listed_pages.iter().filter(|x| !downloaded_pages.contains(x.as_str())).count();
+        let outputs = [("pdf", &filename_pdf), ("cbz", &filename_cbz), ("epub", &filename_epub)]
+            .into_iter()
+            .filter_map(|(format, path)| {
+                let sha256 = file_sha256(std::path::Path::new(path)).ok()?;
+                Some(ArchiveOutput {
+                    format: format.to_string(),
+                    path: path.clone(),
+                    sha256,
+                })
+            })
+            .collect();
+        let entry = ArchiveEntry {
+            status: match pages_missing {
+                0 => ArchiveStatus::Complete,
+                _ => ArchiveStatus::Partial,
+            },
+            title: meta.get_full_title(),
+            book_type: Some(meta.book_type),
+            publish_date: meta.publish_date.clone(),
+            outputs,
+            page_count: Some(manifest.pages.len()),
+            pages_missing: Some(pages_missing),
+            ..ArchiveEntry::completed_now(&id)
+        };
+        if let Err(e) = Archive::append_entry(archive, &entry) {
+            eprintln!("Couldn't write to file: {}", e);
+        }
+    }
+
+    Ok(DownloadStatus::Complete(meta))
+}
+
+/// Gets the metadata, viewable pages and table of contents of the book at the provided URL without downloading
+/// any pages.
+///
+/// # Arguments
+///
+/// * `url` - URL of book.
+/// * `options` - Various options for how to make requests.
+pub fn get_book_info(url: &str, options: &ScraperOptions) -> Result<BookInfo> {
+    let id = id_from_url(url)?;
+    let (doc, metadata) = fetch_about_page(&id, options)?;
+
+    // Fetch JSON to get info about all pages.
+    let res = try_download(&get_json_url(&id, "1", "1", Some(options)), options)?;
+    let issue: IssueJson = serde_json::from_slice(&res.body)?;
+    let toc = extract_toc(&doc, Some(&issue))?.map(|(_, x)| x).unwrap_or_default();
+
+    Ok(BookInfo {
+        metadata,
+        url: url_from_id(&id, Some(options)),
+        available_pages: issue.page.into_iter().map(|x| x.pid).collect(),
+        toc,
+    })
+}
+
+/// Gets the metadata of the book at the provided URL from its About page, without fetching anything else.
+pub fn get_book_metadata(url: &str, options: &ScraperOptions) -> Result<BookMetadata> {
+    let id = id_from_url(url)?;
+    Ok(fetch_about_page(&id, options)?.1)
+}
+
+/// Fetches the About page of a book and parses its metadata.
+fn fetch_about_page(id: &str, options: &ScraperOptions) -> Result<(Html, BookMetadata)> {
+    let url = url_from_id(id, Some(options));
+    if options.verbose {
+        println!("Attempting download of issue page with url: {url}");
+    }
+    let res = try_download(&url, options)?;
+    let doc = Html::parse_document(&res.text());
+    let meta = BookMetadata {
+        source_url: url,
+        ..BookMetadata::from_page(id, &doc)?
+    };
+    Ok((doc, meta))
+}
+
+/// Converts a directory of page images to the requested formats, adding the table of contents and links recorded
+/// in its manifest and any text beside the images.
+///
+/// # Arguments
+///
+/// * `image_dir` - Directory of page images, sorted by filename.
+/// * `output` - Path of output files without an extension, which is added for each format.
+/// * `formats` - Format(s) to convert images to. Existing files are replaced.
+/// * `manifest` - Pages, table of contents and links of the book.
+/// * `meta` - Metadata to embed in output files, if known.
+pub fn convert_image_dir(
+    image_dir: &str,
+    output: &str,
+    formats: FormatFlags,
+    manifest: &Manifest,
+    meta: Option<&BookMetadata>,
+) -> Result<()> {
+    let toc = manifest.table_of_contents();
+    if formats.contains(FormatFlags::Pdf) {
+        println!("Generating PDF...");
+        let links = manifest.page_links();
+        let text = PageTexts::from_image_dir(image_dir)?;
+        let pdf_options = PdfOptions {
+            toc: Some(&toc),
+            metadata: meta,
+            links: Some(&links),
+            text: Some(&text),
+        };
+        create_pdf_with_options(image_dir, &std::format!("{output}.pdf"), &pdf_options)?;
+    }
+    if formats.contains(FormatFlags::Cbz) {
+        println!("Generating CBZ...");
+        let cbz_options = CbzOptions {
+            toc: Some(&toc),
+            metadata: meta,
+        };
+        create_cbz_with_options(image_dir, &std::format!("{output}.cbz"), &cbz_options)?;
+    }
+    if formats.contains(FormatFlags::Epub) {
+        println!("Generating EPUB...");
+        let epub_options = EpubOptions {
+            toc: Some(&toc),
+            metadata: meta,
+        };
+        create_epub_with_options(image_dir, &std::format!("{output}.epub"), &epub_options)?;
+    }
+    Ok(())
+}
+
+/// Progress shared between workers downloading the pages of a book.
+struct PageQueue {
+    /// Page IDs whose JSON has yet to be requested.
+    pages_to_download: VecDeque<String>,
+    /// Page IDs that have been claimed for download by a worker.
+    pages_claimed: HashSet<String>,
+    /// Page number to assign to the next page not included in the original JSON.
+    next_page_number: usize,
+    /// Record of pages downloaded so far, saved after each one completes.
+    manifest: Manifest,
+    /// First error encountered by any worker.
+    error: Option<Error>,
+}
+
+/// Information needed to download the pages of a book.
+struct PageDownloadJob<'a> {
+    id: &'a str,
+    first_page: &'a str,
+    book_type: &'a ContentType,
+    issue_pics_dir: &'a str,
+    /// Absolute page numbers of all pages referenced in the original JSON.
+    page_number_lookup: &'a HashMap<String, usize>,
+    options: &'a ScraperOptions,
+}
+
+impl PageDownloadJob<'_> {
+    /// Downloads all pages of book not already in manifest using up to `options.concurrency` workers.
+    ///
+    /// Returns the manifest with pages ordered by page number.
+    fn run(
+        &self,
+        pages_to_download: VecDeque<String>,
+        next_page_number: usize,
+        manifest: Manifest,
+    ) -> Result<Manifest> {
+        let queue = Mutex::new(PageQueue {
+            pages_to_download,
+            pages_claimed: manifest.pages.iter().map(|x| x.page_id.clone()).collect(),
+            next_page_number: manifest
+                .pages
+                .iter()
+                .map(|x| x.page_number + 1)
+                .fold(next_page_number, usize::max),
+            manifest,
+            error: None,
+        });
+
+        std::thread::scope(|s| {
+            for _ in 0..self.options.concurrency.max(1) {
+                s.spawn(|| self.work(&queue));
+            }
+        });
+
+        let queue = queue.into_inner().unwrap();
+        if let Some(e) = queue.error {
+            return Err(e);
         }
+        let mut manifest = queue.manifest;
+        manifest.pages.sort_by_key(|x| x.page_number);
+        Ok(manifest)
+    }
 
+    /// Requests page JSON from the queue until it is empty or any worker fails.
+    fn work(&self, queue: &Mutex<PageQueue>) {
+        loop {
+            // Get next page ID, skip if already downloaded.
+            let page_id = {
+                let mut queue = queue.lock().unwrap();
+                if queue.error.is_some() {
+                    return;
+                }
+                match queue.pages_to_download.pop_front() {
+                    Some(x) if queue.pages_claimed.contains(&x) => continue,
+                    Some(x) => x,
+                    None => return,
+                }
+            };
+
+            if let Err(e) = self.download_from_page_json(&page_id, queue) {
+                queue.lock().unwrap().error.get_or_insert(e);
+                return;
+            }
+        }
+    }
+
+    /// Fetches JSON for the requested page and downloads any unclaimed images linked in it.
+    fn download_from_page_json(&self, page_id: &str, queue: &Mutex<PageQueue>) -> Result<()> {
         // Fetch JSON for page.
-        let mut res = try_download(
-            &get_json_url(&id, &first_page, &page_id, Some(options)),
-            options.download_attempts,
+        let res = try_download(
+            &get_json_url(self.id, self.first_page, page_id, Some(self.options)),
+            self.options,
         )?;
-        let mut body = String::new();
-        res.read_to_string(&mut body)?;
-        let issue: IssueJson = serde_json::from_str(&body).to_result()?;
+        let issue: IssueJson = serde_json::from_slice(&res.body)?;
 
         // Download images linked in JSON.
         // Note: JSON will contain an entry for every page in book. Requested page should have accompanying source URL, and adjacent pages may as well.
         for page in &issue.page {
-            // Skip if no download link or already downloaded.
-            if page.src.is_none() || pages_downloaded.contains(&page.pid) {
+            // Skip if no download link.
+            if page.src.is_none() {
                 continue;
             }
 
-            let mut standard_download = true;
-            let mut filename = String::new();
-
-            let mut p = 0;
-            let page_number = page_number_lookup.get(&page.pid).unwrap_or_else(|| {
-                // In unlikely case where page ID was not included in original JSON, append to end of known pages.
-                p = i_page;
-                i_page += 1;
-                &p
-            });
-
-            if let ContentType::Newspaper = meta.book_type {
-                // For newspapers, only proceed if this is the requested page or high res info is present.
-                if let Some(npage_info) = page
-                    .additional_info
-                    .as_ref()
-                    .and_then(|x| x.newspaper_json_page_info.as_ref())
-                {
-                    // Segmented download
-                    standard_download = false;
-
-                    let size_info = npage_info
-                        .tile_res
-                        .last()
-                        .to_result("Failed to parse newspaper size info")?;
-
-                    let mut any_png = false;
-                    let mut canvas = image::DynamicImage::new(
-                        size_info.width,
-                        size_info.height,
-                        image::ColorType::Rgb8,
-                    );
-
-                    // Images are segmented into 256x256 chunks. Segments at bottom and right edges of page may be smaller.
-                    const SEGMENT_MAX_W: u32 = 256;
-                    const SEGMENT_MAX_H: u32 = 256;
-
-                    // Segments are grouped into blocks of up to 3x3 for ordering.
-                    const SEGMENT_GROUP_MAX_W: u32 = SEGMENT_MAX_W * 3;
-                    const SEGMENT_GROUP_MAX_H: u32 = SEGMENT_MAX_H * 3;
-
-                    // Both the groups and the segments increment left to right, top to bottom, like so:
-                    //  -----------------------------
-                    // | 00 01 02 | 09 10 11 | 18 19 |
-                    // | 03 04 05 | 12 13 14 | 20 21 |
-                    // | 06 07 08 | 15 16 17 | 22 23 |
-                    // | --------- ---------- ------ |
-                    // | 24 25 26 | 30 31 32 | 36 37 |
-                    // | 27 28 29 | 33 34 35 | 38 39 |
-                    //  -----------------------------
-
-                    let coord_x = npage_info.page_scanjob_coordinates.x;
-                    let coord_y = npage_info.page_scanjob_coordinates.y;
-                    let zoom = size_info.zoom;
-
-                    let src_url = Url::try_from(page.src.as_ref().unwrap().as_str()).to_result()?;
-                    let sig = src_url
-                        .query_pairs()
-                        .find(|x| x.0 == "sig")
-                        .to_result("msg")?
-                        .1
-                        .to_string();
-
-                    let mut i = 0;
-                    let mut y_group = 0;
-                    while y_group < size_info.height {
-                        let mut x_group = 0;
-                        while x_group < size_info.width {
-                            let mut y_segment = y_group;
-                            while (y_segment < size_info.height)
-                                && (y_segment < (y_group + SEGMENT_GROUP_MAX_H))
-                            {
-                                let mut x_segment = x_group;
-                                while (x_segment < size_info.width)
-                                    && (x_segment < (x_group + SEGMENT_GROUP_MAX_W))
-                                {
-                                    // TODO: retries and/or error logging.
-
-                                    // Fetch image segment and determine format.
-                                    let mut res =
-                                        try_download(&std::format!("https://books.google.com/books/content?id={id}&pg={coord_x},{coord_y}&img=1&zoom={zoom}&hl=en&sig={sig}&tid={i}"), 
-                                        options.download_attempts)?;
-                                    let ext = get_image_ext(&res)?;
-                                    any_png |= ext == "png";
-
-                                    // Copy segment to page image.
-                                    let mut buf = vec![];
-                                    _ = res.read_to_end(&mut buf).to_result()?;
-                                    let other = image::load_from_memory(&buf).to_result()?;
-                                    canvas.copy_from(&other, x_segment, y_segment).to_result()?;
-
-                                    i += 1;
-
-                                    x_segment += SEGMENT_MAX_W;
-                                }
-                                y_segment += SEGMENT_MAX_H;
-                            }
-                            x_group += SEGMENT_GROUP_MAX_W;
-                        }
-                        y_group += SEGMENT_GROUP_MAX_H;
+            let newspaper_info = match self.book_type {
+                ContentType::Newspaper => {
+                    // For newspapers, only proceed if this is the requested page or high res info is present.
+                    let info = page
+                        .additional_info
+                        .as_ref()
+                        .and_then(|x| x.newspaper_json_page_info.as_ref());
+                    if info.is_none() && page.pid != page_id {
+                        continue;
                     }
+                    info
+                }
+                _ => None,
+            };
 
-                    filename = generate_image_filename(
-                        page_number,
-                        &page.pid,
-                        if any_png { "png" } else { "jpg" },
-                    );
-                    canvas
-                        .save(std::format!("{issue_pics_dir}/{filename}"))
-                        .to_result()?;
-                } else if page.pid != page_id {
+            // Claim page so no other worker downloads it, skipping if already claimed.
+            let page_number = {
+                let mut queue = queue.lock().unwrap();
+                if !queue.pages_claimed.insert(page.pid.clone()) {
                     continue;
                 }
-            }
-
-            if standard_download {
-                // TODO: retries and/or error logging.
-
-                // Fetch image at highest available resolution.
-                let mut res = try_download(
-                    &std::format!("{}&w=10000", page.src.as_ref().unwrap()),
-                    options.download_attempts,
-                )?;
-
-                // Write to disk.
-                let ext = get_image_ext(&res)?;
-                filename = generate_image_filename(page_number, &page.pid, &ext);
-
-                let out_path = std::format!("{issue_pics_dir}/{filename}");
-
-                if ext == "png" {
-                    // If PNG, ensure 24bpp or else it may not appear correctly in PDF.
-                    // In the future, may want to just save as is and let PDF conversion handle image conversion.
-                    let mut buf = vec![];
-                    _ = res.read_to_end(&mut buf).to_result()?;
-                    let img = image::load_from_memory(&buf).to_result()?;
-                    let img = match img.color() {
-                        ColorType::Rgb8 => img,
-                        _ => {
-                            let mut img_24_bpp = DynamicImage::new_rgb8(img.width(), img.height());
-                            img_24_bpp.copy_from(&img, 0, 0).to_result()?;
-                            img_24_bpp
-                        }
-                    };
-                    img.save(out_path).to_result()?;
-                } else {
-                    if let Ok(mut file) = std::fs::File::create_new(out_path) {
-                        res.copy_to(&mut file).to_result()?;
+                match self.page_number_lookup.get(&page.pid) {
+                    Some(x) => *x,
+                    None => {
+                        // In unlikely case where page ID was not included in original JSON, append to end of known pages.
+                        queue.next_page_number += 1;
+                        queue.next_page_number - 1
                     }
                 }
-            }
+            };
 
-            // If TOC entry exists for page ID, associate filename.
-            if let Some(title) = toc_page_title_lookup.get(&page.pid) {
-                toc.add_page(title, &filename);
-            }
+            let filename = match newspaper_info {
+                Some(npage_info) => self.download_newspaper_page(page, npage_info, page_number)?,
+                None => self.download_standard_page(page, page_number)?,
+            };
 
-            pages_downloaded.insert(page.pid.clone());
+            let path = std::path::Path::new(self.issue_pics_dir).join(&filename);
+            let sha256 = file_sha256(&path)?;
+            let links = page_links(page, &path)?;
+            if self.options.ocr_text {
+                self.download_page_text(&page.pid, &path);
+            }
+            let mut queue = queue.lock().unwrap();
+            queue.manifest.pages.push(ManifestPage {
+                page_number,
+                page_id: page.pid.clone(),
+                filename,
+                sha256,
+                links,
+            });
+            queue.manifest.save(self.issue_pics_dir)?;
         }
+        Ok(())
     }
 
-    if pages_downloaded.is_empty() {
-        return Err(std::io::Error::other(
-            match options.tld.as_str() {
-                ".com" => "No downloadable pages found.",
-                _ => "No downloadable pages found. Setting --tld-override to \".com\" may fix this issue.",
+    /// Downloads the text of a page from its plain text view and saves it beside its image, if Google provides any.
+    ///
+    /// Text is optional, so failures are only reported rather than failing the download.
+    fn download_page_text(&self, page_id: &str, image_path: &std::path::Path) {
+        let result = try_download(&get_text_url(self.id, page_id, Some(self.options)), self.options)
+            .and_then(|res| {
+                let doc = Html::parse_document(&res.text());
+                let mut text = String::new();
+                for paragraph in doc.select(&selector("div.flow p, div.flow br")?) {
+                    if paragraph.value().name() == "br" {
+                        text.push('\n');
+                        continue;
+                    }
+                    text.extend(paragraph.text());
+                    text.push('\n');
+                }
+                if !text.trim().is_empty() {
+                    std::fs::write(image_path.with_extension("txt"), text)?;
+                }
+                Ok(())
+            });
+        if let Err(e) = result {
+            if self.options.verbose {
+                println!("No text downloaded for page {page_id}: {e}");
             }
-        ));
+        }
     }
 
-    // Download any formats not already downloaded.
-    if formats.contains(FormatFlags::Pdf) {
-        println!("Generating PDF...");
-        create_pdf_with_toc(&issue_pics_dir, &filename_pdf, &toc)?;
-    }
-    if formats.contains(FormatFlags::Cbz) {
-        println!("Generating CBZ...");
-        create_cbz(&issue_pics_dir, &filename_cbz)?;
-    }
+    /// Downloads high resolution newspaper page in segments and combines them, returning the filename.
+    fn download_newspaper_page(
+        &self,
+        page: &PageJson,
+        npage_info: &NewspaperJsonPageInfo,
+        page_number: usize,
+    ) -> Result<String> {
+        let id = self.id;
+        let size_info = npage_info
+            .tile_res
+            .last()
+            .ok_or_else(|| Error::MetadataParse("Failed to parse newspaper size info".to_string()))?;
+
+        let mut any_png = false;
+        let mut canvas = image::DynamicImage::new(
+            size_info.width,
+            size_info.height,
+            image::ColorType::Rgb8,
+        );
+
+        // Images are segmented into 256x256 chunks. Segments at bottom and right edges of page may be smaller.
+        const SEGMENT_MAX_W: u32 = 256;
+        const SEGMENT_MAX_H: u32 = 256;
+
+        // Segments are grouped into blocks of up to 3x3 for ordering.
+        const SEGMENT_GROUP_MAX_W: u32 = SEGMENT_MAX_W * 3;
+        const SEGMENT_GROUP_MAX_H: u32 = SEGMENT_MAX_H * 3;
+
+        // Both the groups and the segments increment left to right, top to bottom, like so:
+        //  -----------------------------
+        // | 00 01 02 | 09 10 11 | 18 19 |
+        // | 03 04 05 | 12 13 14 | 20 21 |
+        // | 06 07 08 | 15 16 17 | 22 23 |
+        // | --------- ---------- ------ |
+        // | 24 25 26 | 30 31 32 | 36 37 |
+        // | 27 28 29 | 33 34 35 | 38 39 |
+        //  -----------------------------
+
+        let coord_x = npage_info.page_scanjob_coordinates.x;
+        let coord_y = npage_info.page_scanjob_coordinates.y;
+        let zoom = size_info.zoom;
+        let content_base_url = content_base_url(Some(self.options));
+
+        let src = page.src.as_ref().unwrap();
+        let sig = parse_url(src)?
+            .query_pairs()
+            .find(|x| x.0 == "sig")
+            .ok_or_else(|| Error::InvalidUrl(src.to_string()))?
+            .1
+            .to_string();
+
+        let mut i = 0;
+        let mut y_group = 0;
+        while y_group < size_info.height {
+            let mut x_group = 0;
+            while x_group < size_info.width {
+                let mut y_segment = y_group;
+                while (y_segment < size_info.height)
+                    && (y_segment < (y_group + SEGMENT_GROUP_MAX_H))
+                {
+                    let mut x_segment = x_group;
+                    while (x_segment < size_info.width)
+                        && (x_segment < (x_group + SEGMENT_GROUP_MAX_W))
+                    {
+                        // TODO: retries and/or error logging.
+
+                        // Fetch image segment and determine format.
+                        let res =
+                            try_download_image(&std::format!("{content_base_url}/books/content?id={id}&pg={coord_x},{coord_y}&img=1&zoom={zoom}&hl=en&sig={sig}&tid={i}"),
+                            self.options)?;
+                        let ext = get_image_ext(&res);
+                        any_png |= ext == "png";
+
+                        // Copy segment to page image.
+                        let other = image::load_from_memory(&res.body)?;
+                        canvas.copy_from(&other, x_segment, y_segment)?;
+
+                        i += 1;
+
+                        x_segment += SEGMENT_MAX_W;
+                    }
+                    y_segment += SEGMENT_MAX_H;
+                }
+                x_group += SEGMENT_GROUP_MAX_W;
+            }
+            y_group += SEGMENT_GROUP_MAX_H;
+        }
 
-    // Clean up downloaded images unless option is set or directory already existed.
-    if !(options.keep_images || exists_already) {
-        std::fs::remove_dir_all(&issue_pics_dir)?;
+        let filename = generate_image_filename(
+            &page_number,
+            &page.pid,
+            if any_png { "png" } else { "jpg" },
+        );
+        canvas.save(std::format!("{}/{filename}", self.issue_pics_dir))?;
+        Ok(filename)
     }
 
-    // All done. Add to list of downloaded books and update archive file if applicable.
-    if let Some(downloaded) = already_downloaded {
-        downloaded.insert(id.to_string());
-    }
-    if let Some(archive) = options.archive_file.as_ref() {
-        if let Ok(mut file) = OpenOptions::new().append(true).create(true).open(archive) {
-            if let Err(e) = file.write(std::format!("{id}\n").as_bytes()) {
-                eprintln!("Couldn't write to file: {}", e);
-            }
+    /// Downloads page image at highest available resolution, returning the filename.
+    fn download_standard_page(&self, page: &PageJson, page_number: usize) -> Result<String> {
+        // TODO: retries and/or error logging.
+
+        // Fetch image at highest available resolution.
+        let res = try_download_image(
+            &std::format!("{}&w=10000", page.src.as_ref().unwrap()),
+            self.options,
+        )?;
+
+        // Write to disk.
+        let ext = get_image_ext(&res);
+        let filename = generate_image_filename(&page_number, &page.pid, &ext);
+
+        let out_path = std::format!("{}/{filename}", self.issue_pics_dir);
+
+        if ext == "png" {
+            // If PNG, ensure 24bpp or else it may not appear correctly in PDF.
+            // In the future, may want to just save as is and let PDF conversion handle image conversion.
+            let img = image::load_from_memory(&res.body)?;
+            let img = match img.color() {
+                ColorType::Rgb8 => img,
+                _ => {
+                    let mut img_24_bpp = DynamicImage::new_rgb8(img.width(), img.height());
+                    img_24_bpp.copy_from(&img, 0, 0)?;
+                    img_24_bpp
+                }
+            };
+            img.save(out_path)?;
+        } else {
+            std::fs::write(out_path, &res.body)?;
         }
+        Ok(filename)
     }
+}
 
-    Ok(DownloadStatus::Complete(meta))
+/// Gets the links on a page, scaled from the coordinate space given in its JSON to the size of its downloaded image.
+///
+/// Links are dropped if the JSON doesn't give the width they are relative to, since they can't be placed correctly.
+fn page_links(page: &PageJson, image_path: &std::path::Path) -> Result<Vec<ManifestLink>> {
+    let Some(width) = page.width.filter(|x| *x > 0) else {
+        return Ok(Vec::new());
+    };
+    if page.links.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (image_width, _) = image::image_dimensions(image_path)?;
+    let scale = image_width as f32 / width as f32;
+    Ok(page
+        .links
+        .iter()
+        .filter(|x| x.pid.is_some() || x.url.is_some())
+        .map(|x| ManifestLink {
+            x: x.x * scale,
+            y: x.y * scale,
+            width: x.width * scale,
+            height: x.height * scale,
+            page_id: x.pid.clone(),
+            url: x.url.clone(),
+        })
+        .collect())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scraper::fixtures;
+    use crate::scraper::rate_limit::RateLimiter;
+    use crate::scraper::transport::{Response, Transport};
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use url::Url;
+
+    const FAKE_BASE_URL: &str = "http://gbscraper.test";
+    const FAKE_ID: &str = "FAKEBOOK0001";
 
     /// Time to wait in between requests to hopefuly not get flagged as unusual behavior.
     const WAIT_TIME: u64 = 2000;
 
-    fn pause_between_requests() {
-        std::thread::sleep(std::time::Duration::from_millis(WAIT_TIME));
-    }
-
     #[test]
+    #[ignore = "requires access to books.google.com"]
     fn metadata_parsing() {
+        let rate_limiter = Arc::new(RateLimiter::new(
+            std::time::Duration::from_millis(WAIT_TIME),
+            std::time::Duration::ZERO,
+        ));
+
         // Book
         {
             let id = String::from("XV8XAAAAYAAJ");
             let url = std::format!("https://books.google.com/books?id={id}");
             let dest = ".";
-            let mut options = ScraperOptions::default();
-            options.skip_download = true;
+            let options = ScraperOptions {
+                skip_download: true,
+                rate_limiter: rate_limiter.clone(),
+                ..Default::default()
+            };
 
             let mut description = String::new();
             description.push_str("A literary classic that wasn't recognized for its merits until decades after its publication, Herman Melville's Moby-Dick");
@@ -437,6 +743,7 @@ mod tests {
             description.push_str(" Queequeg, must contend with their increasingly dire journey. The book invariably lands on any short list of the greatest American novels.");
 
             let expected = BookMetadata {
+                source_url: url_from_id(&id, None),
                 id,
                 title: String::from("Moby Dick"),
                 publish_date: String::from(""),
@@ -455,15 +762,16 @@ mod tests {
             assert_eq!(metadata.unwrap(), DownloadStatus::Complete(expected));
         }
 
-        pause_between_requests();
-
         // Magazine
         {
             let id = String::from("CFEEAAAAMBAJ");
             let url = std::format!("https://books.google.com/books?id={id}");
             let dest = ".";
-            let mut options = ScraperOptions::default();
-            options.skip_download = true;
+            let options = ScraperOptions {
+                skip_download: true,
+                rate_limiter: rate_limiter.clone(),
+                ..Default::default()
+            };
 
             let mut description = String::new();
             description.push_str("LIFE Magazine is the treasured photographic magazine that chronicled the 20th Century. It now lives on at LIFE.com,");
@@ -471,6 +779,7 @@ mod tests {
             description.push_str(" photos of today’s people and events. They have free access to share, print and post images for personal use.");
 
             let expected = BookMetadata {
+                source_url: url_from_id(&id, None),
                 id,
                 title: String::from("LIFE"),
                 publish_date: String::from("Oct 3, 1969"),
@@ -489,17 +798,19 @@ mod tests {
             assert_eq!(metadata.unwrap(), DownloadStatus::Complete(expected));
         }
 
-        pause_between_requests();
-
         //Newspaper
         {
             let id = String::from("W4clAAAAIBAJ");
             let url = std::format!("https://books.google.com/books?id={id}");
             let dest = ".";
-            let mut options = ScraperOptions::default();
-            options.skip_download = true;
+            let options = ScraperOptions {
+                skip_download: true,
+                rate_limiter: rate_limiter.clone(),
+                ..Default::default()
+            };
 
             let expected = BookMetadata {
+                source_url: url_from_id(&id, None),
                 id,
                 title: String::from("The Afro American"),
                 publish_date: String::from("Jan 4, 1992"),
@@ -518,4 +829,258 @@ mod tests {
             assert_eq!(metadata.unwrap(), DownloadStatus::Complete(expected));
         }
     }
+
+    /// Serves a book with the given number of pages from memory in place of Google.
+    ///
+    /// JSON for each page links the image of that page and the one after it.
+    fn fake_transport(page_count: usize) -> Arc<dyn Transport> {
+        Arc::new(move |url: &str| -> io::Result<Response> {
+            let url_obj = Url::try_from(url).map_err(io::Error::other)?;
+            let query = |key: &str| {
+                url_obj
+                    .query_pairs()
+                    .find(|x| x.0 == key)
+                    .map(|x| x.1.to_string())
+            };
+            if url_obj.path() == "/content" {
+                let mut buf = io::Cursor::new(vec![]);
+                DynamicImage::new_rgb8(4, 6)
+                    .write_to(&mut buf, image::ImageFormat::Png)
+                    .map_err(io::Error::other)?;
+                return Ok(Response::new(200, buf.into_inner()).with_header("Content-Type", "image/png"));
+            }
+            if query("output").as_deref() == Some("text") {
+                let page_id = query("pg").unwrap_or_default();
+                return Ok(Response::new(
+                    200,
+                    std::format!(r#"<html><body><div class="flow"><p>Text of {page_id}</p></div></body></html>"#),
+                ));
+            }
+            if query("jscmd").is_some() {
+                let requested = query("pg").and_then(|x| x.strip_prefix("PA")?.parse::<usize>().ok());
+                let pages: Vec<_> = (1..=page_count)
+                    .map(|i| match requested {
+                        // First page links to the second and to an external site, in a space half the image's width.
+                        Some(r) if i == 1 && (i == r || i == r + 1) => std::format!(
+                            r#"{{"pid":"PA{i}","src":"{FAKE_BASE_URL}/content?pg=PA{i}","width":2,"links":[
+                                {{"x":0,"y":1,"w":1,"h":1,"pid":"PA2"}},
+                                {{"x":1,"y":0,"w":1,"h":2,"url":"https://example.com/"}}
+                            ]}}"#
+                        ),
+                        Some(r) if i == r || i == r + 1 => {
+                            std::format!(r#"{{"pid":"PA{i}","src":"{FAKE_BASE_URL}/content?pg=PA{i}"}}"#)
+                        }
+                        _ => std::format!(r#"{{"pid":"PA{i}"}}"#),
+                    })
+                    .collect();
+                return Ok(Response::new(
+                    200,
+                    std::format!(r#"{{"page":[{}]}}"#, pages.join(",")),
+                ));
+            }
+            Ok(Response::new(
+                200,
+                std::format!(
+                    r#"<html><body><table id="summary_content_table"><tr><td>
+                    <span class="booktitle">Fake Book</span>
+                    <div class="toc_entry"><a href="{FAKE_BASE_URL}/books?id={FAKE_ID}&pg=PA2">Chapter 1</a></div>
+                    </td></tr></table></body></html>"#
+                ),
+            ))
+        })
+    }
+
+    #[test]
+    fn download_with_custom_transport() {
+        let dest = fixtures::TempDir::new("custom-transport");
+        let archive_path = dest.join("archive.jsonl");
+        let options = ScraperOptions {
+            keep_images: true,
+            formats: FormatFlags::Pdf,
+            archive_file: Some(archive_path.to_str().unwrap().to_string()),
+            ocr_text: true,
+            base_url: Some(FAKE_BASE_URL.to_string()),
+            transport: fake_transport(2),
+            ..Default::default()
+        };
+
+        let url = std::format!("https://books.google.com/books?id={FAKE_ID}");
+        let status = download_issue(&url, dest.to_str().unwrap(), &options).unwrap();
+        let DownloadStatus::Complete(meta) = status else {
+            panic!("book was skipped");
+        };
+        assert_eq!(meta.title, "Fake Book");
+        assert_eq!(meta.url(), std::format!("{FAKE_BASE_URL}/books?id={FAKE_ID}&hl=en"));
+
+        let issue_dir = dest.join(std::format!("Fake Book [{FAKE_ID}]"));
+        assert!(issue_dir.join("00001-PA1.png").exists());
+        assert!(issue_dir.join("00002-PA2.png").exists());
+        assert!(dest.join(std::format!("Fake Book [{FAKE_ID}].pdf")).exists());
+
+        // Links are scaled to the downloaded image.
+        let manifest = Manifest::load(issue_dir.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(manifest.metadata.as_ref(), Some(&meta));
+        let links = &manifest.pages[0].links;
+        assert_eq!(links.len(), 2);
+        assert_eq!([links[0].x, links[0].y, links[0].width, links[0].height], [0., 2., 2., 2.]);
+        assert_eq!(links[0].page_id.as_deref(), Some("PA2"));
+        assert_eq!(links[1].url.as_deref(), Some("https://example.com/"));
+        assert!(manifest.pages[1].links.is_empty());
+
+        // Text is saved beside each image and included in the PDF.
+        assert_eq!(std::fs::read_to_string(issue_dir.join("00002-PA2.txt")).unwrap(), "Text of PA2\n");
+        let pdf = lopdf::Document::load(dest.join(std::format!("Fake Book [{FAKE_ID}].pdf"))).unwrap();
+        assert_eq!(pdf.extract_text(&[1]).unwrap().trim(), "Text of PA1");
+
+        // Download is recorded in the archive along with its output.
+        let archive = Archive::open(&archive_path).unwrap();
+        let entry = archive.get(FAKE_ID).unwrap();
+        assert_eq!(entry.status, ArchiveStatus::Complete);
+        assert_eq!((entry.page_count, entry.pages_missing), (Some(2), Some(0)));
+        assert_eq!(entry.outputs.len(), 1);
+        assert_eq!(entry.outputs[0].format, "pdf");
+        assert!(entry.completed_at().is_some());
+
+        // Archive is checked again before starting, so books added by other processes are skipped unfetched.
+        let offline = ScraperOptions {
+            transport: fixtures::transport(&[]),
+            ..options.clone()
+        };
+        let status = download_issue(&url, dest.to_str().unwrap(), &offline).unwrap();
+        assert!(matches!(status, DownloadStatus::Skipped));
+    }
+
+    #[test]
+    fn book_info_without_download() {
+        let options = ScraperOptions {
+            base_url: Some(FAKE_BASE_URL.to_string()),
+            transport: fake_transport(3),
+            ..Default::default()
+        };
+
+        let url = std::format!("https://books.google.com/books?id={FAKE_ID}");
+        let info = get_book_info(&url, &options).unwrap();
+        assert_eq!(info.metadata.title, "Fake Book");
+        assert_eq!(info.available_pages, ["PA1", "PA2", "PA3"]);
+        assert_eq!(info.toc.len(), 1);
+        assert_eq!(info.toc[0].page_id, "PA2");
+
+        // Metadata fields are flattened alongside the rest of the info.
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["id"], FAKE_ID);
+        assert_eq!(json["book_type"], "book");
+        assert_eq!(serde_json::from_value::<BookInfo>(json).unwrap(), info);
+    }
+
+    #[test]
+    fn concurrent_download() {
+        const PAGE_COUNT: usize = 25;
+        let dest = fixtures::TempDir::new("concurrent-download");
+        let options = ScraperOptions {
+            keep_images: true,
+            formats: FormatFlags::None,
+            concurrency: 4,
+            base_url: Some(FAKE_BASE_URL.to_string()),
+            transport: fake_transport(PAGE_COUNT),
+            ..Default::default()
+        };
+
+        let url = std::format!("https://books.google.com/books?id={FAKE_ID}");
+        download_issue(&url, dest.to_str().unwrap(), &options).unwrap();
+
+        // Every page is downloaded exactly once and numbered by its position in the book.
+        let issue_dir = dest.join(std::format!("Fake Book [{FAKE_ID}]"));
+        let mut filenames: Vec<_> = std::fs::read_dir(&issue_dir)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .filter(|x| x != Manifest::FILENAME)
+            .collect();
+        filenames.sort();
+        let expected: Vec<_> = (1..=PAGE_COUNT)
+            .map(|i| generate_image_filename(&i, &std::format!("PA{i}"), "png"))
+            .collect();
+        assert_eq!(filenames, expected);
+    }
+
+    #[test]
+    fn resume_interrupted_download() {
+        const PAGE_COUNT: usize = 6;
+        let dest = fixtures::TempDir::new("resume-download");
+        let issue_dir = dest.join(std::format!("Fake Book [{FAKE_ID}]"));
+        let url = std::format!("https://books.google.com/books?id={FAKE_ID}");
+
+        // Counts images served and fails once the limit is reached.
+        let counting_transport = |limit: usize| {
+            let inner = fake_transport(PAGE_COUNT);
+            let served = Arc::new(AtomicUsize::new(0));
+            let counter = served.clone();
+            let transport = move |url: &str| -> io::Result<Response> {
+                if url.contains("/content") && counter.fetch_add(1, Ordering::SeqCst) >= limit {
+                    return Err(io::Error::other("connection reset"));
+                }
+                inner.get(url)
+            };
+            (Arc::new(transport), served)
+        };
+
+        // Interrupt download after 3 pages.
+        let (transport, _) = counting_transport(3);
+        let options = ScraperOptions {
+            download_attempts: 1,
+            base_url: Some(FAKE_BASE_URL.to_string()),
+            transport,
+            ..Default::default()
+        };
+        assert!(download_issue(&url, dest.to_str().unwrap(), &options).is_err());
+        let manifest = Manifest::load(issue_dir.to_str().unwrap()).unwrap().unwrap();
+        assert!(!manifest.complete);
+        assert_eq!(manifest.pages.len(), 3);
+
+        // Restarted download only fetches remaining pages, then cleans up after itself.
+        let (transport, served) = counting_transport(usize::MAX);
+        let options = ScraperOptions {
+            transport,
+            ..options
+        };
+        download_issue(&url, dest.to_str().unwrap(), &options).unwrap();
+        assert_eq!(served.load(Ordering::SeqCst), PAGE_COUNT - 3);
+        assert!(dest.join(std::format!("Fake Book [{FAKE_ID}].pdf")).exists());
+        assert!(!issue_dir.exists());
+    }
+
+    #[test]
+    fn error_kinds() {
+        let dest = fixtures::TempDir::new("error-kinds");
+        let url = std::format!("https://books.google.com/books?id={FAKE_ID}");
+        let options = ScraperOptions {
+            base_url: Some(FAKE_BASE_URL.to_string()),
+            transport: fake_transport(0),
+            ..Default::default()
+        };
+        let err = download_issue(&url, dest.to_str().unwrap(), &options).unwrap_err();
+        assert!(matches!(err, Error::NoPagesAvailable { ref id, .. } if id == FAKE_ID));
+        assert!(!err.is_retryable());
+
+        // Failures are recorded in the archive, counting each run in a row that fails.
+        let archive_path = dest.join("archive.jsonl");
+        let options = ScraperOptions {
+            transport: fixtures::transport(&[]),
+            archive_file: Some(archive_path.to_str().unwrap().to_string()),
+            ..options
+        };
+        let err = download_issue(&url, dest.to_str().unwrap(), &options).unwrap_err();
+        assert!(matches!(err, Error::HttpStatus { status: 404, .. }));
+        assert_eq!(err.kind(), "http_status");
+        download_issue(&url, dest.to_str().unwrap(), &options).unwrap_err();
+        let archive = Archive::open(&archive_path).unwrap();
+        let failed: Vec<_> = archive.failed().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].url.as_deref(), Some(url.as_str()));
+        assert_eq!(failed[0].error_kind.as_deref(), Some("http_status"));
+        assert_eq!(failed[0].attempts, Some(2));
+        assert!(!archive.contains(FAKE_ID));
+
+        let err = download_issue("not a url", dest.to_str().unwrap(), &options).unwrap_err();
+        assert!(matches!(err, Error::InvalidUrl(_)));
+    }
 }
@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use super::helpers::*;
+use super::types::*;
+use super::*;
+use crate::error::Result;
+
+/// Series of magazines or newspapers subscribed to, and how far each has been synced.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Subscriptions {
+    /// Subscribed series, in the order they were added.
+    pub series: Vec<Subscription>,
+}
+
+/// Series subscribed to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subscription {
+    /// URL of any issue of series.
+    pub url: String,
+    /// Directory to save issues to, if not the one given when syncing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_dir: Option<String>,
+    /// URL of newest period seen.
+    pub last_period: Option<String>,
+    /// ID of newest issue seen within that period.
+    pub last_issue: Option<String>,
+    /// Time series was last synced, in RFC 3339 format.
+    pub last_synced: Option<String>,
+}
+
+impl Subscriptions {
+    /// Loads subscriptions from file, which need not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Subscriptions> {
+        if !path.as_ref().exists() {
+            return Ok(Subscriptions::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Saves subscriptions to file, replacing any previous version.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomic(path.as_ref(), serde_json::to_string_pretty(self)?)
+    }
+
+    /// Subscribes to the series of the issue at the provided URL, returning false if already subscribed.
+    pub fn add(&mut self, url: &str, target_dir: Option<&str>) -> Result<bool> {
+        let id = id_from_url(url)?;
+        if self.series.iter().any(|x| id_from_url(&x.url).is_ok_and(|x| x == id)) {
+            return Ok(false);
+        }
+        self.series.push(Subscription {
+            url: url.to_string(),
+            target_dir: target_dir.map(String::from),
+            ..Default::default()
+        });
+        Ok(true)
+    }
+
+    /// Unsubscribes from the series with the provided URL or ID, returning false if not subscribed.
+    pub fn remove(&mut self, url_or_id: &str) -> bool {
+        let id = id_from_url(url_or_id).unwrap_or_else(|_| url_or_id.trim().to_string());
+        let before = self.series.len();
+        self.series.retain(|x| id_from_url(&x.url).map_or(true, |x| x != id));
+        self.series.len() != before
+    }
+
+    /// Replaces the subscription with the same URL as the one provided, e.g. after syncing a copy of it.
+    pub fn update(&mut self, subscription: &Subscription) {
+        if let Some(x) = self.series.iter_mut().find(|x| x.url == subscription.url) {
+            *x = subscription.clone();
+        }
+    }
+}
+
+/// Downloads issues of a subscribed series added since it was last synced that match `options.filter`, returning
+/// the number downloaded.
+///
+/// Only the period seen last and any newer ones are checked, starting after the issue seen last. Issues are
+/// remembered as seen up to the first that fails, so it is tried again next time.
+pub fn sync_series(
+    subscription: &mut Subscription,
+    dest: &str,
+    options: &ScraperOptions,
+    already_downloaded: &mut HashSet<String>,
+) -> Result<usize> {
+    let url = sanitize_url(&subscription.url, Some(options))?;
+
+    if options.verbose {
+        println!("Attempting download of base page with url: {url}");
+    }
+
+    // Periods are listed oldest first. If the one seen last is no longer listed, check them all.
+    let periods = get_period_urls(&url, options)?;
+    let last_period = subscription.last_period.as_deref().map(period_key);
+    let start = periods.iter().position(|x| Some(period_key(x)) == last_period).unwrap_or(0);
+
+    let mut downloaded = 0;
+    let mut failed = false;
+    'periods: for period_url in &periods[start..] {
+        if options.verbose {
+            println!("Attempting download of period page with url: {period_url}");
+        }
+        let issues = get_issues_in_period(period_url, options)?;
+        let skip = match Some(period_key(period_url)) == last_period {
+            true => subscription
+                .last_issue
+                .as_ref()
+                .and_then(|last| issues.iter().position(|x| &x.id == last))
+                .map_or(0, |i| i + 1),
+            false => 0,
+        };
+        if !failed && issues.len() <= skip {
+            subscription.last_period = Some(period_url.clone());
+            subscription.last_issue = issues.last().map(|x| x.id.clone());
+        }
+
+        for issue in issues.iter().skip(skip) {
+            if options.filter.limit_reached(downloaded) {
+                if options.verbose {
+                    println!("Reached limit of {downloaded} issue(s).");
+                }
+                break 'periods;
+            }
+
+            // Skip issues whose caption already shows they are out of range without fetching them.
+            let result = match options.filter.matches_date(&PublishDate::parse(&issue.caption)) {
+                Some(false) => Ok(DownloadStatus::Skipped),
+                _ => download_issue_skip_downloaded(&issue.url, dest, options, Some(already_downloaded)),
+            };
+            match result {
+                Ok(DownloadStatus::Complete(_)) => downloaded += 1,
+                Ok(DownloadStatus::Skipped) => {}
+                Err(x) => {
+                    eprintln!("Error downloading issue {}: {}", issue.url, x);
+                    failed = true;
+                }
+            }
+            if !failed {
+                subscription.last_period = Some(period_url.clone());
+                subscription.last_issue = Some(issue.id.clone());
+            }
+        }
+    }
+
+    let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap_or_else(|_| OffsetDateTime::now_utc());
+    subscription.last_synced = now.format(&Rfc3339).ok();
+    Ok(downloaded)
+}
+
+/// Gets the year a period page starts at, or an empty string for the period of the issue it was found from.
+fn period_key(url: &str) -> String {
+    parse_url(url)
+        .ok()
+        .and_then(|x| x.query_pairs().find(|x| x.0 == "atm_aiy").map(|x| x.1.to_string()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+    use crate::scraper::transport::Response;
+    use std::sync::{Arc, Mutex};
+
+    const ABOUT_URL: &str = "https://books.google.us/books?id=CFEEAAAAMBAJ&hl=en";
+
+    #[test]
+    fn sync_from_last_seen_issue() {
+        let period_url = |tld: &str, year: u32| {
+            std::format!("https://books.google{tld}/books?id=CFEEAAAAMBAJ&atm_aiy={year}&hl=en")
+        };
+        let (p1940, p1970) = (period_url(".com", 1940), period_url(".us", 1970));
+        let inner = fixtures::transport(&[
+            (ABOUT_URL, "magazine_about.html"),
+            (&p1940, "magazine_period.html"),
+            (&p1970, "magazine_about.html"),
+        ]);
+        let requested = Arc::new(Mutex::new(Vec::<String>::new()));
+        let log = requested.clone();
+        let transport = move |url: &str| -> std::io::Result<Response> {
+            log.lock().unwrap().push(url.to_string());
+            inner.get(url)
+        };
+        let options = ScraperOptions {
+            download_attempts: 1,
+            transport: Arc::new(transport),
+            ..Default::default()
+        };
+
+        let mut subscriptions = Subscriptions::default();
+        assert!(subscriptions.add(ABOUT_URL, Some("LIFE")).unwrap());
+        assert!(!subscriptions.add("https://books.google.com/books?id=CFEEAAAAMBAJ", None).unwrap());
+        let mut subscription = Subscription {
+            last_period: Some(p1940.clone()),
+            last_issue: Some("CFEEAAAAMBAJ".to_string()),
+            ..subscriptions.series[0].clone()
+        };
+
+        // Only issues after the last one seen are fetched, and one that fails is tried again next time.
+        let dest = fixtures::TempDir::new("sync");
+        let dest = dest.to_str().unwrap();
+        assert_eq!(sync_series(&mut subscription, dest, &options, &mut HashSet::new()).unwrap(), 0);
+        {
+            let requested = requested.lock().unwrap();
+            assert!(!requested.iter().any(|x| x.contains("atm_aiy=1930")));
+            assert!(!requested.iter().any(|x| x.contains("pEEEAAAAMBAJ")));
+            assert!(requested.iter().any(|x| x.contains("yEEEAAAAMBAJ")));
+        }
+        assert_eq!(subscription.last_period.as_deref(), Some(p1940.as_str()));
+        assert_eq!(subscription.last_issue.as_deref(), Some("CFEEAAAAMBAJ"));
+        assert!(subscription.last_synced.is_some());
+
+        // Once it succeeds, newer periods are seen too.
+        let mut already_downloaded = HashSet::from(["yEEEAAAAMBAJ".to_string()]);
+        sync_series(&mut subscription, dest, &options, &mut already_downloaded).unwrap();
+        assert_eq!(subscription.last_period.as_deref(), Some(p1970.as_str()));
+        assert_eq!(subscription.last_issue, None);
+
+        subscriptions.update(&subscription);
+        assert_eq!(subscriptions.series[0].last_period.as_deref(), Some(p1970.as_str()));
+        assert!(subscriptions.remove("CFEEAAAAMBAJ"));
+        assert!(subscriptions.series.is_empty());
+    }
+}
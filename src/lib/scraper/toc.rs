@@ -0,0 +1,421 @@
+use scraper::{ElementRef, Html};
+
+use super::helpers::*;
+use super::manifest::ManifestTocEntry;
+use super::types::IssueJson;
+use crate::error::Result;
+
+/// Layouts of table of contents that Google uses, in the order they are tried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TocLayout {
+    /// `div.toc_entry` rows, each either linking to its page or giving the page number as text.
+    EntryRows,
+    /// Table of contents laid out as a table, with the title and page in separate cells.
+    Table,
+    /// Links listed under a "Contents" heading in the about page, nested with lists.
+    ContentsSection,
+    /// Entries included with the page information of the JSON API.
+    Json,
+}
+
+/// Extracts the table of contents of a book in its original order, trying each layout in turn until one has entries.
+///
+/// # Arguments
+///
+/// * `doc` - About page of book.
+/// * `issue` - Result of JSON API call for book, if available.
+pub(crate) fn extract_toc(
+    doc: &Html,
+    issue: Option<&IssueJson>,
+) -> Result<Option<(TocLayout, Vec<ManifestTocEntry>)>> {
+    let extracted = [
+        (TocLayout::EntryRows, from_entry_rows(doc)?),
+        (TocLayout::Table, from_table(doc)?),
+        (TocLayout::ContentsSection, from_contents_section(doc)?),
+        (TocLayout::Json, issue.map(from_json).unwrap_or_default()),
+    ];
+    Ok(extracted.into_iter().find(|(_, entries)| !entries.is_empty()))
+}
+
+/// Extracts entries from `div.toc_entry` rows, with nesting levels derived from the indentation of each row.
+///
+/// Rows normally contain a link to their page, but some books only give the page number as text at the end of the row.
+pub(crate) fn from_entry_rows(doc: &Html) -> Result<Vec<ManifestTocEntry>> {
+    let mut entries = Vec::<(ManifestTocEntry, f32)>::new();
+    for element in doc.select(&selector("div.toc_entry")?) {
+        let text = element_text(&element);
+        let entry = match link_page_id(&element)? {
+            // Title is the text of the element.
+            Some(page_id) => Some((page_id, text)),
+            // Page number is the last word of the text.
+            None => split_page_number(&text).map(|(title, page_id)| (page_id, title.to_string())),
+        };
+        if let Some((page_id, title)) = entry {
+            let indent = indent(element.attr("style").unwrap_or_default());
+            entries.push((toc_entry(page_id, &title, 0), indent));
+        }
+    }
+    Ok(levels_from_indents(entries))
+}
+
+/// Extracts entries from a table of contents laid out as a table, e.g. `table#toc`, with one row per entry.
+pub(crate) fn from_table(doc: &Html) -> Result<Vec<ManifestTocEntry>> {
+    let mut entries = Vec::<(ManifestTocEntry, f32)>::new();
+    for table in doc.select(&selector("table#toc, table.toc, table.toc_table")?) {
+        for row in table.select(&selector("tr")?) {
+            let cells: Vec<_> = row.select(&selector("td")?).collect();
+            let Some(title_cell) = cells.iter().find(|x| {
+                let text = element_text(x);
+                !text.is_empty() && page_id_from_number(&text).is_none()
+            }) else {
+                continue;
+            };
+            let title = element_text(title_cell);
+            let page_id = match link_page_id(&row)? {
+                Some(x) => Some(x),
+                None => cells.iter().find_map(|x| page_id_from_number(&element_text(x))),
+            };
+            if let Some(page_id) = page_id {
+                let style = title_cell.attr("style").or(row.attr("style")).unwrap_or_default();
+                entries.push((toc_entry(page_id, &title, 0), indent(style)));
+            }
+        }
+    }
+    Ok(levels_from_indents(entries))
+}
+
+/// Extracts links to pages listed under a "Contents" heading, with nesting levels taken from nested lists.
+pub(crate) fn from_contents_section(doc: &Html) -> Result<Vec<ManifestTocEntry>> {
+    let Some(heading) = doc
+        .select(&selector("h1, h2, h3, h4, div.title, div.heading")?)
+        .find(|x| {
+            let text = element_text(x).to_lowercase();
+            text == "contents" || text == "table of contents"
+        })
+    else {
+        return Ok(Vec::new());
+    };
+
+    // Contents are in the first element following the heading that links to any pages.
+    let link_selector = selector("a[href]")?;
+    let Some(section) = heading
+        .next_siblings()
+        .filter_map(ElementRef::wrap)
+        .find(|x| x.select(&link_selector).any(|a| link_page_id(&a).ok().flatten().is_some()))
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut entries = Vec::new();
+    for link in section.select(&link_selector) {
+        let Some(page_id) = link_page_id(&link)? else {
+            continue;
+        };
+        let level = link
+            .ancestors()
+            .take_while(|x| x.id() != section.id())
+            .filter_map(ElementRef::wrap)
+            .filter(|x| x.value().name() == "li")
+            .count()
+            .saturating_sub(1);
+        entries.push(toc_entry(page_id, &element_text(&link), level));
+    }
+    Ok(entries)
+}
+
+/// Extracts entries included in the result of the JSON API call.
+pub(crate) fn from_json(issue: &IssueJson) -> Vec<ManifestTocEntry> {
+    issue
+        .toc
+        .iter()
+        .filter(|x| !x.pid.is_empty())
+        .map(|x| toc_entry(x.pid.clone(), &x.title, x.level))
+        .collect()
+}
+
+fn toc_entry(page_id: String, title: &str, level: usize) -> ManifestTocEntry {
+    ManifestTocEntry {
+        page_id,
+        title: title.to_string(),
+        level,
+    }
+}
+
+/// Gets the text of an element with whitespace collapsed.
+fn element_text(element: &ElementRef) -> String {
+    element.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Gets the page ID from the `pg` parameter of the first link in or on an element.
+fn link_page_id(element: &ElementRef) -> Result<Option<String>> {
+    let href = match element.value().name() {
+        "a" => element.attr("href"),
+        _ => element
+            .select(&selector("a[href]")?)
+            .next()
+            .and_then(|x| x.attr("href")),
+    };
+    let Some(href) = href else {
+        return Ok(None);
+    };
+    // Links may be relative to the page.
+    let url = parse_url(&resolve_url("https://books.google.com/books", href)?)?;
+    Ok(url.query_pairs().find(|x| x.0 == "pg").map(|x| x.1.to_string()))
+}
+
+/// Splits text ending with a page number, e.g. "Loomings 1", into its title and page ID.
+fn split_page_number(text: &str) -> Option<(&str, String)> {
+    let (title, number) = text.trim().rsplit_once(' ')?;
+    let title = title.trim_end_matches(['.', ' ', '\u{2026}']);
+    match title.is_empty() {
+        true => None,
+        false => page_id_from_number(number).map(|x| (title, x)),
+    }
+}
+
+/// Converts a printed page number into Google's page ID, e.g. "12" to "PA12" and "xii" to "PR12".
+///
+/// Only lowercase roman numerals are accepted since uppercase ones are more likely part of a title, e.g. "Part II".
+pub(crate) fn page_id_from_number(text: &str) -> Option<String> {
+    let text = text.trim();
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) {
+        return text.parse::<u32>().ok().map(|x| std::format!("PA{x}"));
+    }
+    if text.chars().any(|c| c.is_uppercase()) {
+        return None;
+    }
+    // Front matter never runs to the hundreds needing "d" or "m", which words such as "mix" would otherwise pass.
+    roman_to_number(text)
+        .filter(|x| *x < MAX_FRONT_MATTER_PAGE)
+        .map(|x| std::format!("PR{x}"))
+}
+
+/// Number of the first page of front matter too high to be numbered with roman numerals.
+const MAX_FRONT_MATTER_PAGE: u32 = 400;
+
+/// Converts a roman numeral to a number, if it is one written in canonical form, e.g. "xiv" but not "xiiii".
+pub(crate) fn roman_to_number(text: &str) -> Option<u32> {
+    let values: Vec<u32> = text
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'i' => Some(1),
+            'v' => Some(5),
+            'x' => Some(10),
+            'l' => Some(50),
+            'c' => Some(100),
+            'd' => Some(500),
+            'm' => Some(1000),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    if values.is_empty() {
+        return None;
+    }
+    let mut total = 0;
+    for (i, value) in values.iter().enumerate() {
+        match values.get(i + 1) {
+            Some(next) if next > value => total -= *value as i64,
+            _ => total += *value as i64,
+        }
+    }
+    // Words such as "mild" and malformed numerals such as "ic" sum to a number but aren't written that way.
+    u32::try_from(total)
+        .ok()
+        .filter(|x| *x > 0 && number_to_roman(*x) == text.to_lowercase())
+}
+
+/// Converts a number to a lowercase roman numeral in canonical form.
+fn number_to_roman(mut number: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut text = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            text.push_str(numeral);
+            number -= value;
+        }
+    }
+    text
+}
+
+/// Gets the indentation of an element from its inline style, e.g. "margin-left:2em".
+fn indent(style: &str) -> f32 {
+    style
+        .split(';')
+        .filter_map(|x| x.split_once(':'))
+        .filter(|(name, _)| matches!(name.trim(), "margin-left" | "padding-left"))
+        .filter_map(|(_, value)| {
+            let value = value.trim();
+            let number = value.trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '%');
+            // Treat em as roughly 16px so mixed units still order sensibly.
+            let scale = if value.ends_with("em") { 16. } else { 1. };
+            number.trim().parse::<f32>().ok().map(|x| x * scale)
+        })
+        .sum()
+}
+
+/// Assigns levels to entries so that each distinct amount of indentation is one level deeper than the amount before it.
+fn levels_from_indents(entries: Vec<(ManifestTocEntry, f32)>) -> Vec<ManifestTocEntry> {
+    let mut indents: Vec<f32> = entries.iter().map(|x| x.1).collect();
+    indents.sort_by(f32::total_cmp);
+    indents.dedup();
+    entries
+        .into_iter()
+        .map(|(mut entry, indent)| {
+            entry.level = indents.iter().position(|x| *x == indent).unwrap_or_default();
+            entry
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+
+    fn summary(toc: &[ManifestTocEntry]) -> Vec<(&str, &str, usize)> {
+        toc.iter()
+            .map(|x| (x.page_id.as_str(), x.title.as_str(), x.level))
+            .collect()
+    }
+
+    #[test]
+    fn entry_rows_with_links() {
+        let doc = fixtures::document("book_about.html");
+        let toc = from_entry_rows(&doc).unwrap();
+        assert_eq!(
+            summary(&toc),
+            [
+                ("PA1", "Loomings", 0),
+                ("PA8", "The Carpet-Bag", 0),
+                ("PA531", "Epilogue", 0),
+            ]
+        );
+
+        let toc = from_entry_rows(&fixtures::document("magazine_about.html")).unwrap();
+        assert_eq!(toc.len(), 3);
+        assert_eq!(toc[1].page_id, "PA32");
+        assert_eq!(toc[1].title, "The Faces of the American Dead in Vietnam");
+
+        let toc = from_entry_rows(&fixtures::document("newspaper_about.html")).unwrap();
+        assert!(toc.is_empty());
+    }
+
+    #[test]
+    fn nested_entry_rows() {
+        // Nesting follows indentation, order is kept and several entries can share a page.
+        let toc = from_entry_rows(&fixtures::document("book_nested_toc_about.html")).unwrap();
+        assert_eq!(
+            summary(&toc),
+            [
+                ("PR5", "Preface", 0),
+                ("PA1", "Part I. Poems", 0),
+                ("PA1", "Early Poems", 1),
+                ("PA3", "To a Skylark", 2),
+                ("PA40", "Later Poems", 1),
+                ("PA120", "Part II. Letters", 0),
+                ("PA121", "To his Father", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn single_row_entries() {
+        let doc = fixtures::document("book_toc_single_row_about.html");
+        let toc = from_entry_rows(&doc).unwrap();
+        assert_eq!(
+            summary(&toc),
+            [
+                ("PR7", "Introduction", 0),
+                ("PA1", "The Voyage Out", 0),
+                ("PA45", "Chapter II", 1),
+                ("PA120", "Letters from the mill", 0),
+                ("PA301", "Index", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn table_layout() {
+        let doc = fixtures::document("book_toc_table_about.html");
+        assert!(from_entry_rows(&doc).unwrap().is_empty());
+        let toc = from_table(&doc).unwrap();
+        assert_eq!(
+            summary(&toc),
+            [
+                ("PR9", "Preface", 0),
+                ("PA3", "On the Origin of Species", 0),
+                ("PA17", "Variation under Domestication", 1),
+                ("PA88", "Struggle for Existence", 1),
+                ("PA140", "mix", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn contents_section_layout() {
+        let doc = fixtures::document("book_toc_contents_section_about.html");
+        assert!(from_entry_rows(&doc).unwrap().is_empty());
+        assert!(from_table(&doc).unwrap().is_empty());
+        let toc = from_contents_section(&doc).unwrap();
+        assert_eq!(
+            summary(&toc),
+            [
+                ("PA1", "Book One", 0),
+                ("PA3", "Chapter 1", 1),
+                ("PA20", "Chapter 2", 1),
+                ("PA45", "Book Two", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_layout() {
+        let issue: IssueJson = serde_json::from_str(&fixtures::read("book_toc_click3.json")).unwrap();
+        let toc = from_json(&issue);
+        assert_eq!(
+            summary(&toc),
+            [("PA1", "Chapter 1", 0), ("PA5", "A Section", 1), ("PA9", "Chapter 2", 0)]
+        );
+
+        // JSON is only used when the about page has no table of contents.
+        let doc = fixtures::document("newspaper_about.html");
+        let (layout, entries) = extract_toc(&doc, Some(&issue)).unwrap().unwrap();
+        assert_eq!(layout, TocLayout::Json);
+        assert_eq!(entries, toc);
+        let doc = fixtures::document("book_about.html");
+        let (layout, _) = extract_toc(&doc, Some(&issue)).unwrap().unwrap();
+        assert_eq!(layout, TocLayout::EntryRows);
+
+        // Other JSON has no table of contents.
+        let issue: IssueJson = serde_json::from_str(&fixtures::read("book_click3.json")).unwrap();
+        assert!(from_json(&issue).is_empty());
+    }
+
+    #[test]
+    fn page_numbers() {
+        assert_eq!(page_id_from_number("12").as_deref(), Some("PA12"));
+        assert_eq!(page_id_from_number("xiv").as_deref(), Some("PR14"));
+        assert_eq!(page_id_from_number("II"), None);
+        assert_eq!(roman_to_number("MCMXCIV"), Some(1994));
+        for word in ["mild", "mill", "civil", "vivid", "dim", "mix", "iiii", "vx", "ic"] {
+            assert_eq!(page_id_from_number(word), None, "{word}");
+        }
+        assert_eq!(page_id_from_number("Index"), None);
+        assert_eq!(page_id_from_number(""), None);
+    }
+}
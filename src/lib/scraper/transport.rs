@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::io::{self};
+use std::sync::OnceLock;
+
+/// Response to a request made through a [`Transport`].
+#[derive(Debug, Clone, Default)]
+pub struct Response {
+    /// HTTP status code.
+    pub status: u16,
+    /// Response headers, keyed by lowercase header name.
+    pub headers: HashMap<String, String>,
+    /// Raw response body.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Creates a response with the provided status code and body and no headers.
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            status,
+            headers: HashMap::new(),
+            body: body.into(),
+        }
+    }
+
+    /// Adds a header to the response.
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.headers.insert(name.to_lowercase(), value.to_string());
+        self
+    }
+
+    /// Gets the value of a header by its case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(|x| x.as_str())
+    }
+
+    /// Gets the body of the response as text, replacing any invalid UTF-8.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Means of fetching resources from Google Books (or a stand-in for it).
+///
+/// All network access made by the scraper goes through the transport set in
+/// [`ScraperOptions::transport`](super::ScraperOptions::transport), so an alternate implementation can be
+/// provided to use a custom client or to serve responses from local fixtures.
+///
+/// Any `Fn(&str) -> io::Result<Response>` closure can be used as a transport.
+pub trait Transport: Send + Sync {
+    /// Performs a GET request for the provided URL.
+    fn get(&self, url: &str) -> io::Result<Response>;
+}
+
+impl<F> Transport for F
+where
+    F: Fn(&str) -> io::Result<Response> + Send + Sync,
+{
+    fn get(&self, url: &str) -> io::Result<Response> {
+        self(url)
+    }
+}
+
+/// Default transport, which performs requests with a blocking `reqwest` client.
+#[derive(Default)]
+pub struct ReqwestTransport {
+    client: OnceLock<reqwest::blocking::Client>,
+}
+
+impl ReqwestTransport {
+    /// Creates a transport that performs requests with the provided client.
+    pub fn with_client(client: reqwest::blocking::Client) -> ReqwestTransport {
+        ReqwestTransport {
+            client: OnceLock::from(client),
+        }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn get(&self, url: &str) -> io::Result<Response> {
+        // Client is created on first use since doing so spins up a runtime.
+        let client = self.client.get_or_init(reqwest::blocking::Client::new);
+        let res = client.get(url).send().map_err(io::Error::other)?;
+        let status = res.status().as_u16();
+        let mut headers = HashMap::new();
+        for (name, value) in res.headers() {
+            if let Ok(value) = value.to_str() {
+                headers.insert(name.as_str().to_lowercase(), value.to_string());
+            }
+        }
+        let body = res.bytes().map_err(io::Error::other)?.to_vec();
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
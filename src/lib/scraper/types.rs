@@ -1,16 +1,28 @@
 use bitflags::bitflags;
 use scraper::selectable::Selectable;
-use scraper::{Html, Selector};
-use std::io::{self};
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
 
 use super::helpers::*;
+use crate::error::{Error, Result};
+use super::manifest::ManifestTocEntry;
+use super::filter::IssueFilter;
+use super::naming::NameTemplate;
+use super::rate_limit::RateLimiter;
+use super::transport::{ReqwestTransport, Transport};
 
 pub use json_api::IssueJson;
+pub use json_api::LinkJson;
+pub use json_api::NewspaperJsonPageInfo;
 pub use json_api::PageJson;
+pub use json_api::TocJson;
 
 pub const FALLBACK_TLD: &str = ".us";
 
 /// Scrape options.
+#[derive(Clone)]
 pub struct ScraperOptions {
     /// If true, downloaded images will not be deleted after conversion.
     pub keep_images: bool,
@@ -22,10 +34,27 @@ pub struct ScraperOptions {
     pub skip_download: bool,
     /// Number of times to attempt to download any file before giving up on a book. Set to 0 to try indefinitely.
     pub download_attempts: u32,
+    /// Time to wait before the first retry of a failed download, doubling with each further attempt.
+    /// Ignored when the server specifies how long to wait.
+    pub retry_backoff: Duration,
+    /// Maximum number of pages to download at once.
+    pub concurrency: usize,
+    /// If true, the text Google provides for each page is downloaded and added to PDFs as a searchable layer.
+    pub ocr_text: bool,
     /// If true, extra output will be given.
     pub verbose: bool,
     /// Top level domain to use for URLs.
     pub tld: String,
+    /// Base URL to send requests to in place of `https://books.google{tld}`, e.g. a local stand-in server.
+    pub base_url: Option<String>,
+    /// Transport used to perform all requests.
+    pub transport: Arc<dyn Transport>,
+    /// Limits how often requests are made through the transport.
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Template for the path of each book relative to the target directory. If `None`, the default for the type of book is used.
+    pub name_template: Option<NameTemplate>,
+    /// Filters selecting which books are downloaded. The limit only applies to period and full download modes.
+    pub filter: IssueFilter,
 }
 
 impl Default for ScraperOptions {
@@ -36,8 +65,16 @@ impl Default for ScraperOptions {
             archive_file: None,
             skip_download: false,
             download_attempts: 3,
+            retry_backoff: Duration::from_secs(1),
+            concurrency: 1,
+            ocr_text: false,
             verbose: false,
             tld: FALLBACK_TLD.to_string(),
+            base_url: None,
+            transport: Arc::new(ReqwestTransport::default()),
+            rate_limiter: Arc::new(RateLimiter::unlimited()),
+            name_template: None,
+            filter: IssueFilter::default(),
         }
     }
 }
@@ -49,12 +86,14 @@ bitflags! {
         const None = 0b000;
         const Pdf =  0b001;
         const Cbz =  0b010;
-        const All =  0b011;
+        const Epub = 0b100;
+        const All =  0b111;
     }
 }
 
 /// Metadata for book or individual issue of magazine.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BookMetadata {
     /// ID used to identify book resource
     pub id: String,
@@ -80,6 +119,8 @@ pub struct BookMetadata {
     pub date_digitized: String,
     /// Source of book
     pub orig_from: String,
+    /// URL of About page metadata was fetched from, using the host the book was downloaded from
+    pub source_url: String,
 }
 
 /// Data types deserializing JSON API calls to get book info.
@@ -90,6 +131,20 @@ mod json_api {
     #[derive(Serialize, Deserialize)]
     pub struct IssueJson {
         pub page: Vec<PageJson>,
+        /// Table of contents, only included for some books.
+        #[serde(default)]
+        pub toc: Vec<TocJson>,
+    }
+
+    /// Table of contents entry.
+    #[derive(Serialize, Deserialize)]
+    pub struct TocJson {
+        #[serde(alias = "label")]
+        pub title: String,
+        #[serde(alias = "page_id")]
+        pub pid: String,
+        #[serde(default, alias = "depth")]
+        pub level: usize,
     }
 
     /// Metadata pertaining to specific page.
@@ -98,6 +153,26 @@ mod json_api {
         pub pid: String,
         pub src: Option<String>,
         pub additional_info: Option<PageAdditionalInfo>,
+        /// Width of page in the coordinate space of its links.
+        pub width: Option<u32>,
+        /// Clickable regions of page.
+        #[serde(default)]
+        pub links: Vec<LinkJson>,
+    }
+
+    /// Clickable region of a page, linking to either another page or an external URL.
+    #[derive(Serialize, Deserialize)]
+    pub struct LinkJson {
+        pub x: f32,
+        pub y: f32,
+        #[serde(rename(deserialize = "w"))]
+        pub width: f32,
+        #[serde(rename(deserialize = "h"))]
+        pub height: f32,
+        /// ID of page linked to.
+        pub pid: Option<String>,
+        /// External URL linked to.
+        pub url: Option<String>,
     }
 
     /// Additional metadata for specific page.
@@ -132,13 +207,117 @@ mod json_api {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Publish date split into its parts, any of which may be missing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PublishDate {
+    pub year: Option<i32>,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+}
+
+impl PublishDate {
+    const MONTHS: [&'static str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    /// Parses a date as displayed by Google Books, such as "Oct 3, 1969", "Sep 1985" or "1985".
+    pub fn parse(text: &str) -> PublishDate {
+        let mut date = PublishDate::default();
+        for token in text.split(|c: char| c.is_whitespace() || c == ',').filter(|x| !x.is_empty()) {
+            let lower = token.to_lowercase();
+            if let Some(i) = Self::MONTHS.iter().position(|x| lower.starts_with(x)) {
+                date.month = Some(i as u8 + 1);
+            } else if let Ok(n) = token.parse::<u32>() {
+                match token.len() {
+                    4 => date.year = Some(n as i32),
+                    1 | 2 if (1..=31).contains(&n) => date.day = Some(n as u8),
+                    _ => {}
+                }
+            }
+        }
+        date
+    }
+
+    /// Parses an ISO 8601 date with any precision, e.g. "1969-10-03", "1969-10" or "1969".
+    ///
+    /// Returns `None` if the date is malformed or out of range.
+    pub fn parse_iso(text: &str) -> Option<PublishDate> {
+        // Parts may be missing, but any that are present must be valid.
+        let number = |part: Option<&str>, max: u8| match part {
+            None => Some(None),
+            Some(x) => x.parse::<u8>().ok().filter(|x| (1..=max).contains(x)).map(Some),
+        };
+        let mut parts = text.trim().splitn(3, '-');
+        let year = parts.next().filter(|x| x.len() == 4)?.parse::<i32>().ok()?;
+        Some(PublishDate {
+            year: Some(year),
+            month: number(parts.next(), 12)?,
+            day: number(parts.next(), 31)?,
+        })
+    }
+
+    /// Gets the earliest day the date could refer to as (year, month, day), if the year is known.
+    pub fn earliest(&self) -> Option<(i32, u8, u8)> {
+        Some((self.year?, self.month.unwrap_or(1), self.day.unwrap_or(1)))
+    }
+
+    /// Gets the latest day the date could refer to as (year, month, day), if the year is known.
+    ///
+    /// Unknown days are taken as the 31st, which still orders correctly against any real date.
+    pub fn latest(&self) -> Option<(i32, u8, u8)> {
+        Some((self.year?, self.month.unwrap_or(12), self.day.unwrap_or(31)))
+    }
+
+    /// Formats the date as ISO 8601 with as much precision as is known, e.g. "1969-10-03" or "1985", if the year is known.
+    pub fn to_iso(&self) -> Option<String> {
+        match (self.year, self.month, self.day) {
+            (Some(y), Some(m), Some(d)) => Some(std::format!("{y:04}-{m:02}-{d:02}")),
+            (Some(y), Some(m), None) => Some(std::format!("{y:04}-{m:02}")),
+            (Some(y), None, _) => Some(std::format!("{y:04}")),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ContentType {
+    #[default]
     Book,
     Magazine,
     Newspaper,
 }
 
+/// Information about a book that can be gathered without downloading any pages.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookInfo {
+    /// Metadata of book.
+    #[serde(flatten)]
+    pub metadata: BookMetadata,
+    /// URL of book's About page.
+    pub url: String,
+    /// IDs of pages that can be viewed, in order.
+    pub available_pages: Vec<String>,
+    /// Table of contents entries, in their original order.
+    pub toc: Vec<ManifestTocEntry>,
+}
+
+/// Issue of a periodical found in the issue gallery of one of its periods.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssueListing {
+    /// ID of issue.
+    pub id: String,
+    /// URL of issue.
+    pub url: String,
+    /// Caption shown below the issue's cover, usually its publish date.
+    pub caption: String,
+    /// URL of period the issue was listed in.
+    pub period_url: String,
+    /// Metadata of issue, if it has been fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<BookMetadata>,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 #[allow(clippy::large_enum_variant)]
 pub enum DownloadStatus {
@@ -177,10 +356,42 @@ impl BookMetadata {
         }
     }
 
-    fn parse_length(text: &str) -> io::Result<u32> {
+    /// Gets the parts of the publish date that could be understood, e.g. "Oct 3, 1969" or "1985".
+    pub fn parsed_publish_date(&self) -> PublishDate {
+        PublishDate::parse(&self.publish_date)
+    }
+
+    /// Splits the volume, e.g. "Vol. 67, No. 14", into its volume and issue numbers.
+    /// Volume text in any other form is returned unchanged as the volume.
+    pub fn parsed_volume(&self) -> (Option<&str>, Option<&str>) {
+        let text = self.volume.trim();
+        let mut volume = None;
+        let mut number = None;
+        for part in text.split(',').map(|x| x.trim()) {
+            if let Some(v) = part.strip_prefix("Vol.") {
+                volume = Some(v.trim());
+            } else if let Some(n) = part.strip_prefix("No.") {
+                number = Some(n.trim());
+            }
+        }
+        if volume.is_none() && number.is_none() && !text.is_empty() {
+            volume = Some(text);
+        }
+        (volume, number)
+    }
+
+    /// Gets the URL of the book's About page, preferring the one metadata was fetched from.
+    pub fn url(&self) -> String {
+        match self.source_url.is_empty() {
+            true => url_from_id(&self.id, None),
+            false => self.source_url.clone(),
+        }
+    }
+
+    fn parse_length(text: &str) -> Result<u32> {
         Self::remove_and_extract(text, Self::SUFFIX_PAGES)
             .parse::<u32>()
-            .to_result()
+            .map_err(|e| Error::MetadataParse(std::format!("Invalid page count \"{text}\": {e}")))
     }
 
     fn remove_and_extract(source: &str, to_remove: &str) -> String {
@@ -188,14 +399,14 @@ impl BookMetadata {
     }
 
     /// Extracts metadata from webpage.
-    pub fn from_page(id: &str, doc: &Html) -> io::Result<BookMetadata> {
+    pub fn from_page(id: &str, doc: &Html) -> Result<BookMetadata> {
         let element = doc
-            .select(&Selector::parse("#summary_content_table").to_result()?)
+            .select(&selector("#summary_content_table")?)
             .next()
-            .to_result("Metadata could not be parsed.")?;
+            .ok_or_else(|| Error::MetadataParse("Metadata could not be parsed.".to_string()))?;
 
         let mut title = match element
-            .select(&Selector::parse(".booktitle").to_result()?)
+            .select(&selector(".booktitle")?)
             .next()
             .and_then(|e| e.text().next())
         {
@@ -204,7 +415,7 @@ impl BookMetadata {
         };
 
         let description = match element
-            .select(&Selector::parse("#synopsistext").to_result()?)
+            .select(&selector("#synopsistext")?)
             .next()
             .and_then(|e| e.text().next())
         {
@@ -224,7 +435,7 @@ impl BookMetadata {
 
         // Main metadata area
         if let Some(e) = element
-            .select(&Selector::parse("#metadata").to_result()?)
+            .select(&selector("#metadata")?)
             .next()
         {
             for (i, child) in e.text().enumerate() {
@@ -243,14 +454,14 @@ impl BookMetadata {
         };
 
         // Bibliography area - used specifically by books?
-        for tr in doc.select(&Selector::parse(".metadata_row").to_result()?) {
+        for tr in doc.select(&selector(".metadata_row")?) {
             if let Some(label) = tr
-                .select(&Selector::parse(".metadata_label").to_result()?)
+                .select(&selector(".metadata_label")?)
                 .next()
                 .and_then(|e| e.text().next())
             {
                 if let Some(value) = tr
-                    .select(&Selector::parse(".metadata_value span").to_result()?)
+                    .select(&selector(".metadata_value span")?)
                     .next()
                     .and_then(|e| e.text().next())
                 {
@@ -286,7 +497,7 @@ impl BookMetadata {
 
         // Determine content type from text in preview link
         let book_type = match doc
-            .select(&Selector::parse("#preview-link span").to_result()?)
+            .select(&selector("#preview-link span")?)
             .next()
             .and_then(|e| e.text().next())
         {
@@ -315,6 +526,146 @@ impl BookMetadata {
             length,
             date_digitized,
             orig_from,
+            ..Default::default()
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+
+    #[test]
+    fn book_metadata_parsing() {
+        let id = String::from("XV8XAAAAYAAJ");
+        let doc = fixtures::document("book_about.html");
+
+        let mut description = String::new();
+        description.push_str("A literary classic that wasn't recognized for its merits until decades after its publication, Herman Melville's Moby-Dick");
+        description.push_str(" tells the tale of a whaling ship and its crew, who are carried progressively further out to sea by the fiery Captain Ahab.");
+        description.push_str(" Obsessed with killing the massive whale, which had previously bitten off Ahab's leg, the seasoned seafarer steers his ship");
+        description.push_str(" to confront the creature, while the rest of the shipmates, including the young narrator, Ishmael, and the harpoon expert,");
+        description.push_str(" Queequeg, must contend with their increasingly dire journey. The book invariably lands on any short list of the greatest American novels.");
+
+        let expected = BookMetadata {
+            id: id.clone(),
+            title: String::from("Moby Dick"),
+            publish_date: String::from(""),
+            volume: String::from(""),
+            issn: String::from(""),
+            publisher: String::from("Dana Estes & Company, 1892"),
+            description,
+            book_type: ContentType::Book,
+            author: String::from("Herman Melville"),
+            length: 545,
+            date_digitized: String::from("Mar 20, 2008"),
+            orig_from: String::from("Harvard University"),
+            source_url: String::new(),
+        };
+
+        assert_eq!(BookMetadata::from_page(&id, &doc).unwrap(), expected);
+    }
+
+    #[test]
+    fn magazine_metadata_parsing() {
+        let id = String::from("CFEEAAAAMBAJ");
+        let doc = fixtures::document("magazine_about.html");
+
+        let mut description = String::new();
+        description.push_str("LIFE Magazine is the treasured photographic magazine that chronicled the 20th Century. It now lives on at LIFE.com,");
+        description.push_str(" the largest, most amazing collection of professional photography on the internet. Users can browse, search and view");
+        description.push_str(" photos of today’s people and events. They have free access to share, print and post images for personal use.");
+
+        let expected = BookMetadata {
+            id: id.clone(),
+            title: String::from("LIFE"),
+            publish_date: String::from("Oct 3, 1969"),
+            volume: String::from("Vol. 67, No. 14"),
+            issn: String::from("0024-3019"),
+            publisher: String::from("Time Inc"),
+            description,
+            book_type: ContentType::Magazine,
+            author: String::from(""),
+            length: 94,
+            date_digitized: String::from(""),
+            orig_from: String::from(""),
+            source_url: String::new(),
+        };
+
+        let metadata = BookMetadata::from_page(&id, &doc).unwrap();
+        assert_eq!(metadata.get_full_title(), "LIFE - Oct 3, 1969");
+        assert_eq!(metadata, expected);
+    }
+
+    #[test]
+    fn newspaper_metadata_parsing() {
+        let id = String::from("W4clAAAAIBAJ");
+        let doc = fixtures::document("newspaper_about.html");
+
+        let expected = BookMetadata {
+            id: id.clone(),
+            title: String::from("The Afro American"),
+            publish_date: String::from("Jan 4, 1992"),
+            volume: String::from(""),
+            issn: String::from(""),
+            publisher: String::from("The Afro American"),
+            description: String::from(""),
+            book_type: ContentType::Newspaper,
+            author: String::from(""),
+            length: 0,
+            date_digitized: String::from(""),
+            orig_from: String::from(""),
+            source_url: String::new(),
+        };
+
+        assert_eq!(BookMetadata::from_page(&id, &doc).unwrap(), expected);
+    }
+
+    #[test]
+    fn metadata_missing() {
+        let doc = Html::parse_document("<html><body><p>Our systems have detected unusual traffic</p></body></html>");
+        assert!(BookMetadata::from_page("FAKE_ID", &doc).is_err());
+    }
+
+    #[test]
+    fn publish_date_parsing() {
+        let date = |year, month, day| PublishDate { year, month, day };
+        assert_eq!(PublishDate::parse("Oct 3, 1969"), date(Some(1969), Some(10), Some(3)));
+        assert_eq!(PublishDate::parse("Sep 1985"), date(Some(1985), Some(9), None));
+        assert_eq!(PublishDate::parse("1985"), date(Some(1985), None, None));
+        assert_eq!(PublishDate::parse(""), date(None, None, None));
+        assert_eq!(PublishDate::parse_iso("1969-10-03"), Some(date(Some(1969), Some(10), Some(3))));
+        assert_eq!(PublishDate::parse_iso("1969"), Some(date(Some(1969), None, None)));
+        assert_eq!(PublishDate::parse_iso("1969-13"), None);
+        assert_eq!(PublishDate::parse_iso("Oct 1969"), None);
+    }
+
+    #[test]
+    fn issue_json_parsing() {
+        // Book: only some pages have sources, unknown fields are ignored.
+        let issue: IssueJson = serde_json::from_str(&fixtures::read("book_click3.json")).unwrap();
+        let pids: Vec<_> = issue.page.iter().map(|x| x.pid.as_str()).collect();
+        assert_eq!(pids, ["PP1", "PP7", "PR1", "PR3", "PA1", "PA2", "PA8"]);
+        assert!(issue.page[0].src.as_ref().unwrap().contains("pg=PP1"));
+        assert!(issue.page[1..].iter().all(|x| x.src.is_none()));
+        assert!(issue.page.iter().all(|x| x.additional_info.is_none()));
+
+        // Magazine
+        let issue: IssueJson = serde_json::from_str(&fixtures::read("magazine_click3.json")).unwrap();
+        assert_eq!(issue.page.len(), 5);
+        assert_eq!(issue.page.iter().filter(|x| x.src.is_some()).count(), 2);
+
+        // Newspaper: high resolution tile info is present for pages with sources.
+        let issue: IssueJson = serde_json::from_str(&fixtures::read("newspaper_click3.json")).unwrap();
+        let info = issue.page[0]
+            .additional_info
+            .as_ref()
+            .and_then(|x| x.newspaper_json_page_info.as_ref())
+            .unwrap();
+        let size_info = info.tile_res.last().unwrap();
+        assert_eq!((size_info.width, size_info.height, size_info.zoom), (2560, 4096, 3));
+        assert_eq!((info.page_scanjob_coordinates.x, info.page_scanjob_coordinates.y), (1234, 5678));
+        assert!(issue.page[1].additional_info.is_none());
+    }
+}
@@ -1,42 +1,174 @@
-use std::{
-    fs,
-    io::{self, Read, Seek, Write},
-};
+use std::io::{self, Read, Seek, Write};
 use zip::write::SimpleFileOptions;
 
+use super::pdf::TableOfContents;
+use super::xml_escape;
+use crate::error::{Error, Result};
+use crate::scraper::BookMetadata;
+
+/// Optional content to include in a CBZ alongside the page images.
+#[derive(Default, Clone, Copy)]
+pub struct CbzOptions<'a> {
+    /// Table of contents, used to bookmark pages in `ComicInfo.xml`.
+    pub toc: Option<&'a TableOfContents>,
+    /// Metadata to write to `ComicInfo.xml`. If `None`, no `ComicInfo.xml` is included.
+    pub metadata: Option<&'a BookMetadata>,
+}
+
+/// Creates a CBZ from images in a specified directory.
+///
+/// # Arguments
+///
+/// * `image_dir` - Directory where images to be converted into pages of CBZ exist.
+/// * `target_filename` - Path to save CBZ to, including filename and extension.
+pub fn create_cbz(image_dir: &str, target_filename: &str) -> Result<()> {
+    create_cbz_with_options(image_dir, target_filename, &CbzOptions::default())
+}
+
 /// Creates a CBZ from images in a specified directory.
 ///
 /// # Arguments
 ///
 /// * `image_dir` - Directory where images to be converted into pages of CBZ exist.
 /// * `target_filename` - Path to save CBZ to, including filename and extension.
-pub fn create_cbz(image_dir: &str, target_filename: &str) -> io::Result<()> {
+/// * `options` - Additional content to include in the CBZ.
+pub fn create_cbz_with_options(
+    image_dir: &str,
+    target_filename: &str,
+    options: &CbzOptions,
+) -> Result<()> {
     let dir_entry = std::path::Path::new(target_filename);
     let file = std::fs::File::create(dir_entry)?;
 
     let mut zip = zip::ZipWriter::new(file);
-    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let zip_options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
 
-    let mut entries: Vec<_> = fs::read_dir(image_dir)?
-        .collect::<io::Result<_>>()?;
-    entries.sort_by_key(|e| e.file_name());
+    let entries = super::read_image_dir(image_dir)?;
+    let mut filenames = Vec::new();
     for dir_entry in entries {
         let mut file = std::fs::File::open(dir_entry.path())?;
         let filename = dir_entry.file_name().into_string().map_err(|file_name| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
+            Error::writer(
+                "CBZ",
                 format!("image filename is not valid UTF-8: {:?}", file_name),
             )
         })?;
         file.seek(io::SeekFrom::Start(0))?;
 
-        zip.start_file(filename, options)?;
+        zip.start_file(&filename, zip_options)
+            .map_err(|e| Error::writer("CBZ", e))?;
 
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
         zip.write_all(&buffer)?;
+        filenames.push(filename);
+    }
+
+    if let Some(meta) = options.metadata {
+        zip.start_file("ComicInfo.xml", zip_options)
+            .map_err(|e| Error::writer("CBZ", e))?;
+        zip.write_all(comic_info(meta, &filenames, options.toc).as_bytes())?;
     }
 
-    zip.finish()?;
+    zip.finish().map_err(|e| Error::writer("CBZ", e))?;
     Ok(())
 }
+
+/// Builds `ComicInfo.xml` describing a book whose pages are the provided images, in order.
+fn comic_info(meta: &BookMetadata, filenames: &[String], toc: Option<&TableOfContents>) -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" ",
+        "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+    ));
+    let mut add = |name: &str, value: &str| {
+        if !value.is_empty() {
+            xml.push_str(&format!("  <{name}>{}</{name}>\n", xml_escape(value)));
+        }
+    };
+
+    let date = meta.parsed_publish_date();
+    let (volume, number) = meta.parsed_volume();
+    add("Title", &meta.get_full_title());
+    add("Series", &meta.title);
+    add("Number", number.unwrap_or_default());
+    // Volume must be an integer, so it's omitted if it couldn't be parsed as one.
+    add(
+        "Volume",
+        &volume.and_then(|x| x.parse::<u32>().ok()).map(|x| x.to_string()).unwrap_or_default(),
+    );
+    add("Summary", &meta.description);
+    add("Year", &date.year.map(|x| x.to_string()).unwrap_or_default());
+    add("Month", &date.month.map(|x| x.to_string()).unwrap_or_default());
+    add("Day", &date.day.map(|x| x.to_string()).unwrap_or_default());
+    add("Writer", &meta.author);
+    add("Publisher", &meta.publisher);
+    add("Web", &meta.url());
+    add("PageCount", &filenames.len().to_string());
+
+    xml.push_str("  <Pages>\n");
+    for (i, filename) in filenames.iter().enumerate() {
+        xml.push_str(&format!("    <Page Image=\"{i}\""));
+        if i == 0 {
+            xml.push_str(" Type=\"FrontCover\"");
+        }
+        if let Some(title) = toc.and_then(|x| x.page_title(filename)) {
+            xml.push_str(&format!(" Bookmark=\"{}\"", xml_escape(title)));
+        }
+        xml.push_str(" />\n");
+    }
+    xml.push_str("  </Pages>\n</ComicInfo>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+    use crate::scraper::ContentType;
+
+    #[test]
+    fn comic_info_written() {
+        let dir = fixtures::TempDir::new("cbz-comic-info");
+        for name in ["0001.png", "0002.png", "0003.png"] {
+            image::RgbImage::new(4, 6).save(dir.join(name)).unwrap();
+        }
+
+        let meta = BookMetadata {
+            id: String::from("N0EEAAAAMBAJ"),
+            title: String::from("LIFE"),
+            publish_date: String::from("Oct 3, 1969"),
+            volume: String::from("Vol. 67, No. 14"),
+            publisher: String::from("Time Inc"),
+            book_type: ContentType::Magazine,
+            ..Default::default()
+        };
+        let mut toc = TableOfContents::new();
+        toc.add_page("Letters & Notes", "0002.png");
+        let target = dir.join("out.cbz");
+        let options = CbzOptions {
+            toc: Some(&toc),
+            metadata: Some(&meta),
+        };
+        create_cbz_with_options(dir.to_str().unwrap(), target.to_str().unwrap(), &options).unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&target).unwrap()).unwrap();
+        assert_eq!(archive.len(), 4);
+        let mut xml = String::new();
+        archive.by_name("ComicInfo.xml").unwrap().read_to_string(&mut xml).unwrap();
+        for expected in [
+            "<Series>LIFE</Series>",
+            "<Number>14</Number>",
+            "<Volume>67</Volume>",
+            "<Year>1969</Year>",
+            "<Month>10</Month>",
+            "<Day>3</Day>",
+            "<PageCount>3</PageCount>",
+            "<Web>https://books.google",
+            "<Page Image=\"0\" Type=\"FrontCover\" />",
+            "<Page Image=\"1\" Bookmark=\"Letters &amp; Notes\" />",
+        ] {
+            assert!(xml.contains(expected), "missing {expected} in {xml}");
+        }
+    }
+}
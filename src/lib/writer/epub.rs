@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use zip::write::SimpleFileOptions;
+
+use super::pdf::{TableOfContents, TocEntry};
+use super::xml_escape;
+use crate::error::{Error, Result};
+use crate::scraper::BookMetadata;
+
+/// Optional content to include in an EPUB alongside the page images.
+#[derive(Default, Clone, Copy)]
+pub struct EpubOptions<'a> {
+    /// Table of contents used to build the navigation document.
+    pub toc: Option<&'a TableOfContents>,
+    /// Metadata to write to the package document.
+    pub metadata: Option<&'a BookMetadata>,
+}
+
+/// Image included in the EPUB as a page.
+struct EpubPage {
+    /// Original filename of image.
+    filename: String,
+    /// Media type of image.
+    media_type: &'static str,
+    width: u32,
+    height: u32,
+}
+
+impl EpubPage {
+    fn image_href(&self) -> String {
+        format!("images/{}", self.filename)
+    }
+
+    fn page_href(&self, index: usize) -> String {
+        format!("pages/page-{:04}.xhtml", index + 1)
+    }
+}
+
+/// Creates a fixed-layout EPUB 3 from images in a specified directory, with one page per image.
+///
+/// # Arguments
+///
+/// * `image_dir` - Directory where images to be converted into pages of EPUB exist.
+/// * `target_filename` - Path to save EPUB to, including filename and extension.
+pub fn create_epub(image_dir: &str, target_filename: &str) -> Result<()> {
+    create_epub_with_options(image_dir, target_filename, &EpubOptions::default())
+}
+
+/// Creates a fixed-layout EPUB 3 from images in a specified directory, with one page per image.
+///
+/// # Arguments
+///
+/// * `image_dir` - Directory where images to be converted into pages of EPUB exist.
+/// * `target_filename` - Path to save EPUB to, including filename and extension.
+/// * `options` - Additional content to include in the EPUB.
+pub fn create_epub_with_options(
+    image_dir: &str,
+    target_filename: &str,
+    options: &EpubOptions,
+) -> Result<()> {
+    let file = std::fs::File::create(target_filename)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let deflated = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    // The mimetype must come first and be stored uncompressed so readers can identify the file.
+    let stored = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    start_file(&mut zip, "mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    start_file(&mut zip, "META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    let mut pages = Vec::new();
+    for dir_entry in super::read_image_dir(image_dir)? {
+        let path = dir_entry.path();
+        let filename = dir_entry.file_name().into_string().map_err(|file_name| {
+            Error::writer(
+                "EPUB",
+                format!("image filename is not valid UTF-8: {:?}", file_name),
+            )
+        })?;
+        let (width, height) = image::image_dimensions(&path)?;
+        let page = EpubPage {
+            media_type: media_type(&filename),
+            filename,
+            width,
+            height,
+        };
+
+        let mut buffer = Vec::new();
+        std::fs::File::open(&path)?.read_to_end(&mut buffer)?;
+        start_file(&mut zip, &format!("OEBPS/{}", page.image_href()), deflated)?;
+        zip.write_all(&buffer)?;
+
+        start_file(&mut zip, &format!("OEBPS/{}", page.page_href(pages.len())), deflated)?;
+        zip.write_all(page_xhtml(&page, pages.len()).as_bytes())?;
+        pages.push(page);
+    }
+    if pages.is_empty() {
+        return Err(Error::writer("EPUB", "no images found"));
+    }
+
+    start_file(&mut zip, "OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(nav_xhtml(&pages, options.toc).as_bytes())?;
+
+    start_file(&mut zip, "OEBPS/content.opf", deflated)?;
+    zip.write_all(package_opf(&pages, options.metadata, target_filename).as_bytes())?;
+
+    zip.finish().map_err(|e| Error::writer("EPUB", e))?;
+    Ok(())
+}
+
+fn start_file(
+    zip: &mut zip::ZipWriter<std::fs::File>,
+    name: &str,
+    options: SimpleFileOptions,
+) -> Result<()> {
+    zip.start_file(name, options)
+        .map_err(|e| Error::writer("EPUB", e))
+}
+
+/// Gets the media type of an image from its extension.
+fn media_type(filename: &str) -> &'static str {
+    let ext = filename.rsplit('.').next().unwrap_or_default().to_lowercase();
+    match ext.as_str() {
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "tif" | "tiff" => "image/tiff",
+        _ => "image/jpeg",
+    }
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// Builds the XHTML document displaying a single page image at its full size.
+fn page_xhtml(page: &EpubPage, index: usize) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>Page {number}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; width: {width}px; height: {height}px; }}</style>
+</head>
+<body>
+  <img src="../{src}" alt="Page {number}"/>
+</body>
+</html>
+"#,
+        number = index + 1,
+        width = page.width,
+        height = page.height,
+        src = xml_escape(&page.image_href()),
+    )
+}
+
+/// Builds the navigation document from the table of contents, keeping its order and nesting.
+fn nav_xhtml(pages: &[EpubPage], toc: Option<&TableOfContents>) -> String {
+    let hrefs: HashMap<&str, String> = pages
+        .iter()
+        .enumerate()
+        .map(|(i, page)| (page.filename.as_str(), page.page_href(i)))
+        .collect();
+    let mut items = String::new();
+    if let Some(toc) = toc {
+        nav_items(&mut items, toc.entries(), &hrefs, 3);
+    }
+    // Navigation document must have at least one entry.
+    if items.is_empty() {
+        items = format!("      <li><a href=\"{}\">Start</a></li>\n", pages[0].page_href(0));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>Contents</title>
+</head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>Contents</h1>
+    <ol>
+{items}    </ol>
+  </nav>
+</body>
+</html>
+"#
+    )
+}
+
+/// Writes list items for table of contents entries and their children, skipping any whose image isn't in the EPUB.
+fn nav_items(out: &mut String, entries: &[TocEntry], hrefs: &HashMap<&str, String>, depth: usize) {
+    for entry in entries {
+        let indent = "  ".repeat(depth);
+        let Some(href) = hrefs.get(entry.page_filename.as_str()) else {
+            // Children of a skipped entry are moved up to its level.
+            nav_items(out, &entry.children, hrefs, depth);
+            continue;
+        };
+        out.push_str(&format!(
+            "{indent}<li><a href=\"{}\">{}</a>",
+            xml_escape(href),
+            xml_escape(&entry.page_title)
+        ));
+        let mut children = String::new();
+        nav_items(&mut children, &entry.children, hrefs, depth + 2);
+        if !children.is_empty() {
+            out.push_str(&format!("\n{indent}  <ol>\n{children}{indent}  </ol>\n{indent}"));
+        }
+        out.push_str("</li>\n");
+    }
+}
+
+/// Builds the package document listing every page, populated from book metadata if available.
+fn package_opf(pages: &[EpubPage], meta: Option<&BookMetadata>, target_filename: &str) -> String {
+    let mut metadata = String::new();
+    let mut add = |name: &str, attributes: &str, value: &str| {
+        if !value.is_empty() {
+            metadata.push_str(&format!("    <{name}{attributes}>{}</{name}>\n", xml_escape(value)));
+        }
+    };
+    match meta {
+        Some(meta) => {
+            add("dc:identifier", " id=\"book-id\"", &meta.url());
+            add("dc:title", "", &meta.get_full_title());
+            add("dc:creator", "", &meta.author);
+            add("dc:publisher", "", &meta.publisher);
+            add("dc:description", "", &meta.description);
+            add("dc:date", "", &meta.parsed_publish_date().to_iso().unwrap_or_default());
+            add("dc:source", "", &meta.orig_from);
+        }
+        None => {
+            let title = std::path::Path::new(target_filename)
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .unwrap_or("Untitled");
+            add("dc:identifier", " id=\"book-id\"", &format!("urn:gbscraper:{title}"));
+            add("dc:title", "", title);
+        }
+    }
+    // Language isn't known, so it's marked as undetermined.
+    add("dc:language", "", "und");
+    add("meta", " property=\"dcterms:modified\"", &modified_timestamp());
+    add("meta", " property=\"rendition:layout\"", "pre-paginated");
+    add("meta", " property=\"rendition:orientation\"", "auto");
+    add("meta", " property=\"rendition:spread\"", "auto");
+
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    let mut spine = String::new();
+    for (i, page) in pages.iter().enumerate() {
+        let number = i + 1;
+        let cover = if i == 0 { " properties=\"cover-image\"" } else { "" };
+        manifest.push_str(&format!(
+            "    <item id=\"image-{number}\" href=\"{}\" media-type=\"{}\"{cover}/>\n",
+            xml_escape(&page.image_href()),
+            page.media_type
+        ));
+        manifest.push_str(&format!(
+            "    <item id=\"page-{number}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            page.page_href(i)
+        ));
+        spine.push_str(&format!("    <itemref idref=\"page-{number}\"/>\n"));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{metadata}  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#
+    )
+}
+
+/// Gets the current time in the form required by `dcterms:modified`.
+fn modified_timestamp() -> String {
+    let now = time::OffsetDateTime::now_utc();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+    use crate::scraper::ContentType;
+
+    #[test]
+    fn fixed_layout_epub() {
+        let dir = fixtures::TempDir::new("epub");
+        for name in ["0001.png", "0002.png"] {
+            image::RgbImage::new(4, 6).save(dir.join(name)).unwrap();
+        }
+
+        let meta = BookMetadata {
+            id: String::from("N0EEAAAAMBAJ"),
+            title: String::from("LIFE"),
+            publish_date: String::from("Oct 3, 1969"),
+            book_type: ContentType::Magazine,
+            ..Default::default()
+        };
+        let mut toc = TableOfContents::new();
+        toc.add_page("Cover", "0001.png");
+        toc.add_page_at_level("Letters", "0002.png", 1);
+        let target = dir.join("out.epub");
+        let options = EpubOptions {
+            toc: Some(&toc),
+            metadata: Some(&meta),
+        };
+        create_epub_with_options(dir.to_str().unwrap(), target.to_str().unwrap(), &options)
+            .unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&target).unwrap()).unwrap();
+        let read = |archive: &mut zip::ZipArchive<std::fs::File>, name: &str| {
+            let mut text = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut text).unwrap();
+            text
+        };
+
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        assert_eq!(read(&mut archive, "mimetype"), "application/epub+zip");
+
+        let opf = read(&mut archive, "OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>LIFE - Oct 3, 1969</dc:title>"));
+        assert!(opf.contains("<dc:date>1969-10-03</dc:date>"));
+        assert!(opf.contains("<meta property=\"rendition:layout\">pre-paginated</meta>"));
+        assert!(opf.contains("<dc:identifier id=\"book-id\">https://books.google"));
+        assert!(opf.contains("<itemref idref=\"page-2\"/>"));
+
+        let nav = read(&mut archive, "OEBPS/nav.xhtml");
+        assert!(nav.contains("<li><a href=\"pages/page-0001.xhtml\">Cover</a>\n"));
+        assert!(nav.contains("<ol>\n          <li><a href=\"pages/page-0002.xhtml\">Letters</a></li>\n"));
+
+        let page = read(&mut archive, "OEBPS/pages/page-0001.xhtml");
+        assert!(page.contains("<meta name=\"viewport\" content=\"width=4, height=6\"/>"));
+        assert!(page.contains("src=\"../images/0001.png\""));
+    }
+}
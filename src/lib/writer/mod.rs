@@ -1,2 +1,34 @@
 pub mod cbz;
+pub mod epub;
+pub mod ocr;
 pub mod pdf;
+
+use std::{fs, io};
+
+/// File extensions of images that are converted into pages.
+const IMAGE_EXTENSIONS: [&str; 8] = ["jpg", "jpeg", "png", "gif", "webp", "bmp", "tif", "tiff"];
+
+/// Gets the image files in a directory, sorted by filename. Other files, such as manifests, are ignored.
+pub(crate) fn read_image_dir(image_dir: &str) -> io::Result<Vec<fs::DirEntry>> {
+    let mut entries: Vec<_> = fs::read_dir(image_dir)?
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|e| {
+            e.path()
+                .extension()
+                .and_then(|x| x.to_str())
+                .is_some_and(|x| IMAGE_EXTENSIONS.contains(&x.to_lowercase().as_str()))
+        })
+        .collect();
+    entries.sort_by_key(|e| e.file_name());
+    Ok(entries)
+}
+
+/// Escapes text for inclusion in XML.
+pub(crate) fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
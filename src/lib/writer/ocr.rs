@@ -0,0 +1,216 @@
+use scraper::{ElementRef, Html};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::scraper::helpers::selector;
+
+/// Text recognized on a page, positioned over its image.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageText {
+    /// Words or lines of text and where they appear on the page.
+    pub words: Vec<TextWord>,
+}
+
+/// Piece of text and the region of the page image it covers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextWord {
+    pub text: String,
+    /// X, Y, width and height of region in pixels of the page image, from its top left corner.
+    pub rect: [f32; 4],
+}
+
+/// Text for each page of a book, keyed by image filename.
+#[derive(Default)]
+pub struct PageTexts {
+    lookup: HashMap<String, PageText>,
+}
+
+impl PageText {
+    /// Reads the words of an hOCR document, i.e. `ocrx_word` elements with a `bbox` in their title.
+    pub fn from_hocr(hocr: &str) -> Result<PageText> {
+        let doc = Html::parse_document(hocr);
+        let words = doc
+            .select(&selector(".ocrx_word")?)
+            .filter_map(|element| {
+                let text = element_text(&element);
+                let bbox = element
+                    .attr("title")?
+                    .split(';')
+                    .find_map(|x| x.trim().strip_prefix("bbox "))?
+                    .split_whitespace()
+                    .map(|x| x.parse::<f32>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                match (text.is_empty(), bbox.as_slice()) {
+                    (false, [x0, y0, x1, y1]) => Some(TextWord {
+                        text,
+                        rect: [*x0, *y0, x1 - x0, y1 - y0],
+                    }),
+                    _ => None,
+                }
+            })
+            .collect();
+        Ok(PageText { words })
+    }
+
+    /// Reads the words of an ALTO document, i.e. `String` elements, scaling their positions to the width of the page
+    /// image so any measurement unit can be used.
+    pub fn from_alto(alto: &str, image_width: u32) -> Result<PageText> {
+        let doc = Html::parse_document(alto);
+        let number = |element: &ElementRef, name: &str| element.attr(name)?.parse::<f32>().ok();
+        let scale = doc
+            .select(&selector("page")?)
+            .next()
+            .and_then(|x| number(&x, "width"))
+            .filter(|x| *x > 0.)
+            .map_or(1., |x| image_width as f32 / x);
+        let words = doc
+            .select(&selector("string")?)
+            .filter_map(|element| {
+                let text = element.attr("content")?.trim().to_string();
+                let rect = [
+                    number(&element, "hpos")? * scale,
+                    number(&element, "vpos")? * scale,
+                    number(&element, "width")? * scale,
+                    number(&element, "height")? * scale,
+                ];
+                (!text.is_empty()).then_some(TextWord { text, rect })
+            })
+            .collect();
+        Ok(PageText { words })
+    }
+
+    /// Lays out plain text, which has no positions, as evenly spaced lines across the page so it can still be
+    /// searched and roughly selected.
+    pub fn from_plain(text: &str, image_width: u32, image_height: u32) -> PageText {
+        // Lines are given a fixed minimum spacing so short pages aren't stretched to fill the whole height.
+        const MIN_LINES: usize = 50;
+        let lines: Vec<&str> = text.lines().map(|x| x.trim()).filter(|x| !x.is_empty()).collect();
+        let margin_x = image_width as f32 * 0.05;
+        let margin_y = image_height as f32 * 0.05;
+        let line_height = (image_height as f32 - 2. * margin_y) / lines.len().max(MIN_LINES) as f32;
+        let words = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| TextWord {
+                text: line.to_string(),
+                rect: [
+                    margin_x,
+                    margin_y + i as f32 * line_height,
+                    image_width as f32 - 2. * margin_x,
+                    line_height,
+                ],
+            })
+            .collect();
+        PageText { words }
+    }
+
+    /// Loads text for an image from a file beside it with the same name, if one exists.
+    ///
+    /// Files ending in `.hocr` are read as hOCR, `.xml` as ALTO and `.txt` as plain text, in that order of preference.
+    pub fn from_sidecar(image_path: &Path) -> Result<Option<PageText>> {
+        let hocr = image_path.with_extension("hocr");
+        if hocr.exists() {
+            return Self::from_hocr(&std::fs::read_to_string(hocr)?).map(Some);
+        }
+        let alto = image_path.with_extension("xml");
+        if alto.exists() {
+            let (width, _) = image::image_dimensions(image_path)?;
+            return Self::from_alto(&std::fs::read_to_string(alto)?, width).map(Some);
+        }
+        let plain = image_path.with_extension("txt");
+        if plain.exists() {
+            let (width, height) = image::image_dimensions(image_path)?;
+            return Ok(Some(Self::from_plain(&std::fs::read_to_string(plain)?, width, height)));
+        }
+        Ok(None)
+    }
+}
+
+impl PageTexts {
+    pub fn new() -> PageTexts {
+        Self::default()
+    }
+
+    /// Loads text for every image in a directory that has a text file beside it. See [`PageText::from_sidecar`].
+    pub fn from_image_dir(image_dir: &str) -> Result<PageTexts> {
+        let mut texts = PageTexts::new();
+        for entry in super::read_image_dir(image_dir)? {
+            if let Some(text) = PageText::from_sidecar(&entry.path())? {
+                texts.add_page(&entry.file_name().to_string_lossy(), text);
+            }
+        }
+        Ok(texts)
+    }
+
+    /// Sets the text of a page.
+    pub fn add_page(&mut self, page_filename: &str, text: PageText) {
+        self.lookup.insert(page_filename.to_string(), text);
+    }
+
+    /// Returns true if no page has any text.
+    pub fn is_empty(&self) -> bool {
+        self.lookup.is_empty()
+    }
+
+    /// Gets the text of a page, if it has any.
+    pub fn get(&self, page_filename: &str) -> Option<&PageText> {
+        self.lookup.get(page_filename)
+    }
+}
+
+/// Gets the text of an element with whitespace collapsed.
+fn element_text(element: &ElementRef) -> String {
+    element.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hocr_words() {
+        let hocr = r#"<html><body><div class="ocr_page" title="bbox 0 0 800 1000">
+            <span class="ocr_line" title="bbox 10 20 300 50">
+                <span class="ocrx_word" title="bbox 10 20 100 50; x_wconf 95">Call</span>
+                <span class="ocrx_word" title="bbox 110 20 160 50; x_wconf 96">me</span>
+                <span class="ocrx_word" title="x_wconf 96">Ishmael.</span>
+            </span></div></body></html>"#;
+        let text = PageText::from_hocr(hocr).unwrap();
+        assert_eq!(
+            text.words,
+            [
+                TextWord { text: "Call".to_string(), rect: [10., 20., 90., 30.] },
+                TextWord { text: "me".to_string(), rect: [110., 20., 50., 30.] },
+            ]
+        );
+    }
+
+    #[test]
+    fn alto_words() {
+        let alto = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <alto xmlns="http://www.loc.gov/standards/alto/ns-v4#"><Layout>
+            <Page WIDTH="400" HEIGHT="500"><PrintSpace><TextBlock><TextLine>
+                <String CONTENT="Call" HPOS="5" VPOS="10" WIDTH="45" HEIGHT="15"/>
+                <SP/>
+                <String CONTENT="me" HPOS="55" VPOS="10" WIDTH="25" HEIGHT="15"/>
+            </TextLine></TextBlock></PrintSpace></Page></Layout></alto>"#;
+        let text = PageText::from_alto(alto, 800).unwrap();
+        assert_eq!(
+            text.words,
+            [
+                TextWord { text: "Call".to_string(), rect: [10., 20., 90., 30.] },
+                TextWord { text: "me".to_string(), rect: [110., 20., 50., 30.] },
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_text_lines() {
+        let text = PageText::from_plain("Call me Ishmael.\n\nSome years ago\n", 1000, 1000);
+        assert_eq!(text.words.len(), 2);
+        assert_eq!(text.words[0].text, "Call me Ishmael.");
+        assert_eq!(text.words[0].rect, [50., 50., 900., 18.]);
+        assert_eq!(text.words[1].rect, [50., 68., 900., 18.]);
+    }
+}
@@ -1,29 +1,41 @@
 use lopdf::content::{Content, Operation};
 use lopdf::{dictionary, Bookmark};
 use lopdf::{Document, Object, Stream};
+use lopdf::ObjectId;
 use std::collections::HashMap;
-use std::{fs, io};
+
+use crate::error::{Error, Result};
+use super::ocr::{PageText, PageTexts};
+use super::xml_escape;
+use crate::scraper::{BookMetadata, ContentType};
 
 /// Table of contents for mapping image files to page names.
+///
+/// Entries are kept in the order they were added and may be nested, with any number of entries linking to the same image.
 pub struct TableOfContents {
-    lookup: HashMap<String, TocEntry>,
+    entries: Vec<TocEntry>,
 }
 
-struct TocEntry {
+pub(crate) struct TocEntry {
     pub page_title: String,
+    /// Filename of image linked to.
+    pub page_filename: String,
     /// 0, 1 for italic, 2 for bold, 3 for italic bold
     pub format: u32,
     /// R,G,B
     pub color: [f32; 3],
-    // TODO: descendants???
+    /// Entries nested below this one.
+    pub children: Vec<TocEntry>,
 }
 
 impl TocEntry {
-    fn new(page_title: String, format: u32, color: [f32; 3]) -> TocEntry {
+    fn new(page_title: String, page_filename: String, format: u32, color: [f32; 3]) -> TocEntry {
         TocEntry {
             page_title,
+            page_filename,
             format,
             color,
+            children: Vec::new(),
         }
     }
 }
@@ -37,24 +49,36 @@ impl Default for TableOfContents {
 impl TableOfContents {
     pub fn new() -> TableOfContents {
         TableOfContents {
-            lookup: HashMap::<String, TocEntry>::new(),
+            entries: Vec::<TocEntry>::new(),
         }
     }
 
-    /// Adds entry to table of contents.
+    /// Adds top level entry to end of table of contents.
     ///
     /// # Arguments
     ///
     /// * `page_title` - Title of page as it will appear in document outline.
     /// * `page_filename` - Filename of image to link to.
     pub fn add_page(&mut self, page_title: &str, page_filename: &str) {
+        self.add_page_at_level(page_title, page_filename, 0);
+    }
+
+    /// Adds entry to end of table of contents, nested below the last entry of the level above it.
+    ///
+    /// # Arguments
+    ///
+    /// * `page_title` - Title of page as it will appear in document outline.
+    /// * `page_filename` - Filename of image to link to.
+    /// * `level` - Nesting level of entry, starting at 0 for top level. Levels deeper than the last entry allows are
+    ///   nested directly below it.
+    pub fn add_page_at_level(&mut self, page_title: &str, page_filename: &str, level: usize) {
         self.add_page_internal(
-            page_filename,
-            TocEntry::new(page_title.to_string(), 0, [0., 0., 0.]),
+            level,
+            TocEntry::new(page_title.to_string(), page_filename.to_string(), 0, [0., 0., 0.]),
         );
     }
 
-    /// Adds entry to table of contents.
+    /// Adds top level entry to end of table of contents.
     ///
     /// # Arguments
     ///
@@ -70,28 +94,119 @@ impl TableOfContents {
         color: [f32; 3],
     ) {
         self.add_page_internal(
-            page_filename,
-            TocEntry::new(page_title.to_string(), format, color),
+            0,
+            TocEntry::new(page_title.to_string(), page_filename.to_string(), format, color),
         );
     }
 
-    fn add_page_internal(&mut self, page_filename: &str, entry: TocEntry) {
-        self.lookup.insert(page_filename.to_string(), entry);
+    /// Returns true if there are no entries in the table of contents.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn add_page_internal(&mut self, level: usize, entry: TocEntry) {
+        let mut siblings = &mut self.entries;
+        for _ in 0..level {
+            if siblings.is_empty() {
+                break;
+            }
+            siblings = &mut siblings.last_mut().unwrap().children;
+        }
+        siblings.push(entry);
     }
 
-    fn get_page_info(&self, page_filename: &String) -> Option<&TocEntry> {
-        self.lookup.get(page_filename)
+    /// Gets the title of the first entry for an image, if there is one.
+    pub fn page_title(&self, page_filename: &str) -> Option<&str> {
+        fn find<'a>(entries: &'a [TocEntry], page_filename: &str) -> Option<&'a str> {
+            entries.iter().find_map(|x| {
+                (x.page_filename == page_filename)
+                    .then_some(x.page_title.as_str())
+                    .or_else(|| find(&x.children, page_filename))
+            })
+        }
+        find(&self.entries, page_filename)
+    }
+
+    /// Gets the top level entries.
+    pub(crate) fn entries(&self) -> &[TocEntry] {
+        &self.entries
     }
 }
 
+/// Clickable regions of pages, keyed by image filename.
+#[derive(Default)]
+pub struct PageLinks {
+    lookup: HashMap<String, Vec<PageLink>>,
+}
+
+/// Clickable region of a page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageLink {
+    /// X, Y, width and height of region in pixels of the page image, from its top left corner.
+    pub rect: [f32; 4],
+    /// What the region links to.
+    pub target: LinkTarget,
+}
+
+/// Destination of a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    /// Page of the document, by the filename of its image.
+    Page(String),
+    /// External URI.
+    Uri(String),
+}
+
+impl PageLinks {
+    pub fn new() -> PageLinks {
+        Self::default()
+    }
+
+    /// Adds link to page.
+    ///
+    /// # Arguments
+    ///
+    /// * `page_filename` - Filename of image the link is on.
+    /// * `link` - Region and target of link.
+    pub fn add_link(&mut self, page_filename: &str, link: PageLink) {
+        self.lookup
+            .entry(page_filename.to_string())
+            .or_default()
+            .push(link);
+    }
+
+    /// Returns true if no page has any links.
+    pub fn is_empty(&self) -> bool {
+        self.lookup.is_empty()
+    }
+
+    /// Gets links on a page.
+    pub fn get(&self, page_filename: &str) -> &[PageLink] {
+        self.lookup.get(page_filename).map(|x| x.as_slice()).unwrap_or_default()
+    }
+}
+
+/// Optional content to include in a PDF alongside the page images.
+#[derive(Default, Clone, Copy)]
+pub struct PdfOptions<'a> {
+    /// Table of contents mapping image files to page titles.
+    pub toc: Option<&'a TableOfContents>,
+    /// Metadata to write to the document information dictionary and XMP metadata stream.
+    pub metadata: Option<&'a BookMetadata>,
+    /// Links to add to pages as link annotations.
+    pub links: Option<&'a PageLinks>,
+    /// Text to overlay on pages as an invisible, searchable layer.
+    pub text: Option<&'a PageTexts>,
+}
+
 /// Creates a PDF from images in a specified directory.
 ///
 /// # Arguments
 ///
 /// * `image_dir` - Directory where images to be converted into pages of PDF exist.
 /// * `target_filename` - Path to save PDF to, including filename and extension.
-pub fn create_pdf(image_dir: &str, target_filename: &str) -> io::Result<()> {
-    create_pdf_internal(image_dir, target_filename, None)
+pub fn create_pdf(image_dir: &str, target_filename: &str) -> Result<()> {
+    create_pdf_with_options(image_dir, target_filename, &PdfOptions::default())
 }
 
 /// Creates a PDF from images in a specified directory.
@@ -105,15 +220,26 @@ pub fn create_pdf_with_toc(
     image_dir: &str,
     target_filename: &str,
     toc: &TableOfContents,
-) -> io::Result<()> {
-    create_pdf_internal(image_dir, target_filename, Some(toc))
+) -> Result<()> {
+    let options = PdfOptions {
+        toc: Some(toc),
+        ..Default::default()
+    };
+    create_pdf_with_options(image_dir, target_filename, &options)
 }
 
-fn create_pdf_internal(
+/// Creates a PDF from images in a specified directory.
+///
+/// # Arguments
+///
+/// * `image_dir` - Directory where images to be converted into pages of PDF exist.
+/// * `target_filename` - Path to save PDF to, including filename and extension.
+/// * `options` - Additional content to include in the PDF.
+pub fn create_pdf_with_options(
     image_dir: &str,
     target_filename: &str,
-    toc: Option<&TableOfContents>,
-) -> io::Result<()> {
+    options: &PdfOptions,
+) -> Result<()> {
     // Initialize document
     let mut doc = Document::with_version("1.5");
     let pages_id = doc.new_object_id();
@@ -121,6 +247,7 @@ fn create_pdf_internal(
         "Type" => "Font",
         "Subtype" => "Type1",
         "BaseFont" => "Courier",
+        "Encoding" => "WinAnsiEncoding",
     });
     let resources_id = doc.add_object(dictionary! {
         "Font" => dictionary! {
@@ -130,42 +257,29 @@ fn create_pdf_internal(
 
     // Add page for each image
     let mut pages = vec![];
-    let mut entries: Vec<_> = fs::read_dir(image_dir)?
-        .collect::<io::Result<_>>()?;
-    entries.sort_by_key(|e| e.file_name());
+    let mut page_ids = HashMap::<String, ObjectId>::new();
+    let mut labels = Vec::<Option<PageLabel>>::new();
+    let mut page_heights = Vec::<(String, ObjectId, f32)>::new();
+    let entries = super::read_image_dir(image_dir)?;
     for p in entries {
         let name = p.file_name().into_string().map_err(|file_name| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
+            Error::writer(
+                "PDF",
                 format!("image filename is not valid UTF-8: {:?}", file_name),
             )
         })?;
 
         let image_path = p.path();
         let image_path_str = image_path.to_str().ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
+            Error::writer(
+                "PDF",
                 format!("image path is not valid UTF-8: {:?}", image_path),
             )
         })?;
 
         let stream = lopdf::xobject::image(image_path_str).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("failed to load image '{}': {e}", name),
-            )
+            Error::writer("PDF", format!("failed to load image '{}': {e}", name))
         })?;
-        let content = Content {
-            operations: Vec::<Operation>::new(),
-        };
-        let encoded_content = content.encode().map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("failed to encode PDF content stream for {}: {e}", name),
-            )
-        })?;
-        let content_id = doc.add_object(Stream::new(dictionary! {}, encoded_content));
-
         let mut width: i64 = 800;
         let mut height: i64 = 1100;
         if let Ok(Object::Integer(a)) = stream.dict.get("Width".as_bytes()) {
@@ -175,12 +289,32 @@ fn create_pdf_internal(
             height = *a;
         }
 
-        let image_filename = doc.add_object(dictionary! {
+        let page_text = options.text.and_then(|x| x.get(&name));
+        let content = Content {
+            operations: page_text
+                .map(|x| text_operations(x, height as f32))
+                .unwrap_or_default(),
+        };
+        let encoded_content = content.encode().map_err(|e| {
+            Error::writer(
+                "PDF",
+                format!("failed to encode PDF content stream for {}: {e}", name),
+            )
+        })?;
+        let content_id = doc.add_object(Stream::new(dictionary! {}, encoded_content));
+
+        let mut page = dictionary! {
             "Type" => "Page",
             "Parent" => pages_id,
             "Contents" => content_id,
             "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
-        });
+        };
+        if page_text.is_some() {
+            // Resources of the page replace rather than add to those inherited, so the font must be included once the
+            // image gives the page its own.
+            page.set("Resources", dictionary! { "Font" => dictionary! { "F1" => font_id } });
+        }
+        let image_filename = doc.add_object(page);
 
         doc.insert_image(
             image_filename,
@@ -189,26 +323,13 @@ fn create_pdf_internal(
             (width as f32, height as f32),
         )
         .map_err(|err| {
-            io::Error::other(format!("failed to insert image '{name}' into PDF: {err}"))
+            Error::writer("PDF", format!("failed to insert image '{name}' into PDF: {err}"))
         })?;
 
         pages.push(image_filename.into());
-
-        // Check for TOC entry for this page
-        if let Some(t) = toc {
-            if let Some(value) = t.get_page_info(&name) {
-                let b = Bookmark::new(
-                    value.page_title.clone(),
-                    value.color,
-                    value.format,
-                    image_filename,
-                );
-                doc.add_bookmark(b, None);
-            }
-        }
-
-        //TODO: links in page
-        //Note: may need to download image without setting "w=3000" first in order to scale coordinates
+        labels.push(PageLabel::from_filename(&name));
+        page_ids.insert(name.clone(), image_filename);
+        page_heights.push((name, image_filename, height as f32));
     }
 
     // Finalize and save document
@@ -222,23 +343,535 @@ fn create_pdf_internal(
             "Resources" => resources_id,
         }),
     );
-    let outline_id = doc.build_outline();
-    if let Some(ol) = outline_id {
-        let catalog_id = doc.add_object(dictionary! {
-            "Type" => "Catalog",
-            "Pages" => pages_id,
-            "Outlines" => ol,
-        });
-        doc.trailer.set("Root", catalog_id);
-    } else {
-        let catalog_id = doc.add_object(dictionary! {
-            "Type" => "Catalog",
-            "Pages" => pages_id,
-        });
-        doc.trailer.set("Root", catalog_id);
+    if let Some(t) = options.toc {
+        add_bookmarks(&mut doc, t.entries(), None, &page_ids);
     }
+    if let Some(links) = options.links {
+        for (name, page_id, height) in &page_heights {
+            add_link_annotations(&mut doc, *page_id, *height, links.get(name), &page_ids)?;
+        }
+    }
+    let mut catalog = dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    };
+    if let Some(ol) = doc.build_outline() {
+        catalog.set("Outlines", ol);
+    }
+    if let Some(page_labels) = page_labels(&labels) {
+        catalog.set("PageLabels", page_labels);
+    }
+    if let Some(meta) = options.metadata {
+        let info_id = doc.add_object(info_dictionary(meta));
+        doc.trailer.set("Info", info_id);
+        // Left uncompressed so the metadata can be found by tools that don't parse PDFs.
+        let xmp = Stream::new(
+            dictionary! {
+                "Type" => "Metadata",
+                "Subtype" => "XML",
+            },
+            xmp_metadata(meta).into_bytes(),
+        )
+        .with_compression(false);
+        catalog.set("Metadata", doc.add_object(xmp));
+    }
+    let catalog_id = doc.add_object(catalog);
+    doc.trailer.set("Root", catalog_id);
 
     doc.compress();
     doc.save(target_filename)?;
     Ok(())
 }
+
+/// Adds bookmarks for table of contents entries and their children, skipping any whose image isn't in the PDF.
+fn add_bookmarks(
+    doc: &mut Document,
+    entries: &[TocEntry],
+    parent: Option<u32>,
+    page_ids: &HashMap<String, ObjectId>,
+) {
+    for entry in entries {
+        // Children of a skipped entry are attached to its parent instead.
+        let id = page_ids.get(&entry.page_filename).map(|page_id| {
+            let b = Bookmark::new(entry.page_title.clone(), entry.color, entry.format, *page_id);
+            doc.add_bookmark(b, parent)
+        });
+        add_bookmarks(doc, &entry.children, id.or(parent), page_ids);
+    }
+}
+
+/// Builds content stream operations drawing the text of a page invisibly over the region each word covers.
+fn text_operations(text: &PageText, page_height: f32) -> Vec<Operation> {
+    // Glyphs of Courier are all 0.6 of the font size wide, so each word can be stretched to fit its region exactly.
+    const COURIER_WIDTH: f32 = 0.6;
+    let mut operations = vec![
+        Operation::new("BT", vec![]),
+        // Render mode 3 is neither filled nor stroked, i.e. invisible.
+        Operation::new("Tr", vec![3.into()]),
+    ];
+    for word in &text.words {
+        let [x, y, width, height] = word.rect;
+        let encoded = win_ansi_encode(&word.text);
+        if encoded.is_empty() || width <= 0. || height <= 0. {
+            continue;
+        }
+        let scale = 100. * width / (encoded.len() as f32 * COURIER_WIDTH * height);
+        operations.push(Operation::new("Tf", vec!["F1".into(), height.into()]));
+        operations.push(Operation::new("Tz", vec![scale.into()]));
+        // Baseline is placed a little above the bottom of the region to leave room for descenders.
+        let baseline = page_height - y - height * 0.8;
+        operations.push(Operation::new(
+            "Tm",
+            vec![1.into(), 0.into(), 0.into(), 1.into(), x.into(), baseline.into()],
+        ));
+        operations.push(Operation::new("Tj", vec![Object::string_literal(encoded)]));
+    }
+    operations.push(Operation::new("ET", vec![]));
+    operations
+}
+
+/// Encodes text for a font using WinAnsiEncoding, replacing unsupported characters with "?".
+fn win_ansi_encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            // Latin-1 matches WinAnsiEncoding other than in the range 0x80-0x9F.
+            0x20..=0x7E | 0xA0..=0xFF => c as u8,
+            _ => match c {
+                '\u{2018}' => 0x91,
+                '\u{2019}' => 0x92,
+                '\u{201C}' => 0x93,
+                '\u{201D}' => 0x94,
+                '\u{2013}' => 0x96,
+                '\u{2014}' => 0x97,
+                '\u{2026}' => 0x85,
+                _ => b'?',
+            },
+        })
+        .collect()
+}
+
+/// Adds link annotations to a page, skipping any that link to pages not in the PDF.
+fn add_link_annotations(
+    doc: &mut Document,
+    page_id: ObjectId,
+    page_height: f32,
+    links: &[PageLink],
+    page_ids: &HashMap<String, ObjectId>,
+) -> Result<()> {
+    let mut annots = Vec::<Object>::new();
+    for link in links {
+        // Image coordinates start at the top of the page, while PDF coordinates start at the bottom.
+        let [x, y, width, height] = link.rect;
+        let rect: Vec<Object> = vec![
+            x.into(),
+            (page_height - y - height).into(),
+            (x + width).into(),
+            (page_height - y).into(),
+        ];
+        let mut annot = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => rect,
+            "Border" => vec![0.into(), 0.into(), 0.into()],
+        };
+        match &link.target {
+            LinkTarget::Page(filename) => match page_ids.get(filename) {
+                Some(target) => annot.set("Dest", vec![(*target).into(), "Fit".into()]),
+                None => continue,
+            },
+            LinkTarget::Uri(uri) => annot.set(
+                "A",
+                dictionary! {
+                    "S" => "URI",
+                    "URI" => Object::string_literal(uri.as_str()),
+                },
+            ),
+        }
+        annots.push(doc.add_object(annot).into());
+    }
+    if !annots.is_empty() {
+        doc.get_object_mut(page_id)
+            .and_then(|x| x.as_dict_mut())
+            .map_err(|e| Error::writer("PDF", format!("failed to add links to page: {e}")))?
+            .set("Annots", annots);
+    }
+    Ok(())
+}
+
+/// Printed page number of a page, derived from the Google page ID kept in its image filename.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PageLabel {
+    /// Front matter numbered with lowercase roman numerals, from a `PR` page ID.
+    Roman(u32),
+    /// Numbered page, from a `PA` page ID.
+    Arabic(u32),
+    /// Unnumbered page such as a cover or plate, labeled with its page ID.
+    Other(String),
+}
+
+impl PageLabel {
+    /// Gets the label for an image named as the scraper names them, e.g. "00012-PA3.png".
+    fn from_filename(filename: &str) -> Option<PageLabel> {
+        let (stem, _) = filename.rsplit_once('.')?;
+        let (_, page_id) = stem.split_once('-')?;
+        let number = |prefix: &str| page_id.strip_prefix(prefix).and_then(|x| x.parse::<u32>().ok());
+        if let Some(n) = number("PR") {
+            Some(PageLabel::Roman(n))
+        } else if let Some(n) = number("PA") {
+            Some(PageLabel::Arabic(n))
+        } else if !page_id.is_empty() {
+            Some(PageLabel::Other(page_id.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if this label is the next in sequence after another one.
+    fn follows(&self, previous: &PageLabel) -> bool {
+        match (previous, self) {
+            (PageLabel::Roman(a), PageLabel::Roman(b)) | (PageLabel::Arabic(a), PageLabel::Arabic(b)) => a + 1 == *b,
+            _ => false,
+        }
+    }
+}
+
+/// Builds the page labels number tree, starting a new range wherever numbering changes style or skips pages.
+///
+/// Returns `None` if no page has a printed page number, since labels would add nothing over physical numbering.
+fn page_labels(labels: &[Option<PageLabel>]) -> Option<lopdf::Dictionary> {
+    if !labels
+        .iter()
+        .any(|x| matches!(x, Some(PageLabel::Roman(_) | PageLabel::Arabic(_))))
+    {
+        return None;
+    }
+    let mut nums = Vec::<Object>::new();
+    let mut previous: Option<&PageLabel> = None;
+    for (i, label) in labels.iter().enumerate() {
+        let continues = match (previous, label) {
+            (Some(previous), Some(label)) => label.follows(previous),
+            _ => false,
+        };
+        if !continues {
+            let range = match label {
+                Some(PageLabel::Roman(n)) => dictionary! { "S" => "r", "St" => *n as i64 },
+                Some(PageLabel::Arabic(n)) => dictionary! { "S" => "D", "St" => *n as i64 },
+                Some(PageLabel::Other(page_id)) => dictionary! { "P" => text_string(page_id) },
+                // Fall back to physical page number for images not named by the scraper.
+                None => dictionary! { "S" => "D", "St" => i as i64 + 1 },
+            };
+            nums.push((i as i64).into());
+            nums.push(range.into());
+        }
+        previous = label.as_ref();
+    }
+    Some(dictionary! { "Nums" => nums })
+}
+
+/// Builds the document information dictionary for a book.
+fn info_dictionary(meta: &BookMetadata) -> lopdf::Dictionary {
+    let mut info = dictionary! {
+        "Title" => text_string(&meta.get_full_title()),
+        "Producer" => text_string(concat!("gbscraper ", env!("CARGO_PKG_VERSION"))),
+    };
+    let optional = [
+        ("Author", &meta.author),
+        ("Subject", &meta.description),
+        ("Publisher", &meta.publisher),
+        ("ISSN", &meta.issn),
+        ("Volume", &meta.volume),
+        ("PublishDate", &meta.publish_date),
+    ];
+    for (key, value) in optional {
+        if !value.is_empty() {
+            info.set(key, text_string(value));
+        }
+    }
+    info
+}
+
+/// Encodes text as a PDF text string, using UTF-16 if it can't be represented in ASCII.
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(|x| x.to_be_bytes()));
+    Object::string_literal(bytes)
+}
+
+/// Builds an XMP packet describing a book with Dublin Core, adding PRISM properties for periodicals.
+fn xmp_metadata(meta: &BookMetadata) -> String {
+    let mut props = String::new();
+    let mut add = |name: &str, value: String| {
+        props.push_str(&format!("      <{name}>{value}</{name}>\n"));
+    };
+    let alt = |x: &str| {
+        format!("<rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt>", xml_escape(x))
+    };
+    let seq = |x: &str| format!("<rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq>", xml_escape(x));
+    let bag = |x: &str| format!("<rdf:Bag><rdf:li>{}</rdf:li></rdf:Bag>", xml_escape(x));
+
+    let iso_date = meta.parsed_publish_date().to_iso();
+
+    add("dc:format", "application/pdf".to_string());
+    add("dc:title", alt(&meta.get_full_title()));
+    add("dc:identifier", xml_escape(&meta.id));
+    if !meta.author.is_empty() {
+        add("dc:creator", seq(&meta.author));
+    }
+    if !meta.publisher.is_empty() {
+        add("dc:publisher", bag(&meta.publisher));
+    }
+    if !meta.description.is_empty() {
+        add("dc:description", alt(&meta.description));
+    }
+    if let Some(d) = iso_date.as_ref() {
+        add("dc:date", seq(d));
+    }
+    if !meta.orig_from.is_empty() {
+        add("dc:source", xml_escape(&meta.orig_from));
+    }
+    add("pdf:Producer", xml_escape(concat!("gbscraper ", env!("CARGO_PKG_VERSION"))));
+
+    if let ContentType::Magazine | ContentType::Newspaper = meta.book_type {
+        let aggregation_type = match meta.book_type {
+            ContentType::Newspaper => "newspaper",
+            _ => "magazine",
+        };
+        add("prism:aggregationType", aggregation_type.to_string());
+        add("prism:publicationName", xml_escape(&meta.title));
+        if !meta.issn.is_empty() {
+            add("prism:issn", xml_escape(&meta.issn));
+        }
+        let (volume, number) = meta.parsed_volume();
+        if let Some(v) = volume {
+            add("prism:volume", xml_escape(v));
+        }
+        if let Some(n) = number {
+            add("prism:number", xml_escape(n));
+        }
+        if let Some(d) = iso_date.as_ref() {
+            add("prism:coverDate", d.clone());
+        }
+        if !meta.publish_date.is_empty() {
+            add("prism:coverDisplayDate", xml_escape(&meta.publish_date));
+        }
+    }
+
+    let bom = '\u{feff}';
+    format!(
+        r#"<?xpacket begin="{bom}" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about=""
+        xmlns:dc="http://purl.org/dc/elements/1.1/"
+        xmlns:pdf="http://ns.adobe.com/pdf/1.3/"
+        xmlns:prism="http://prismstandard.org/namespaces/basic/2.0/">
+{props}    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::fixtures;
+
+    #[test]
+    fn metadata_written() {
+        let dir = fixtures::TempDir::new("pdf-metadata");
+        image::RgbImage::new(4, 6).save(dir.join("0001.png")).unwrap();
+
+        let meta = BookMetadata {
+            id: String::from("N0EEAAAAMBAJ"),
+            title: String::from("LIFE"),
+            publish_date: String::from("Oct 3, 1969"),
+            volume: String::from("Vol. 67, No. 14"),
+            issn: String::from("0024-3019"),
+            publisher: String::from("Time Inc & Co"),
+            description: String::from("Caf\u{e9} society"),
+            book_type: ContentType::Magazine,
+            ..Default::default()
+        };
+        let target = dir.join("out.pdf");
+        let options = PdfOptions {
+            metadata: Some(&meta),
+            ..Default::default()
+        };
+        create_pdf_with_options(dir.to_str().unwrap(), target.to_str().unwrap(), &options).unwrap();
+
+        let doc = Document::load(&target).unwrap();
+        let info_id = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        let info = doc.get_dictionary(info_id).unwrap();
+        assert_eq!(info.get(b"Title").unwrap().as_str().unwrap(), b"LIFE - Oct 3, 1969");
+        assert_eq!(info.get(b"ISSN").unwrap().as_str().unwrap(), b"0024-3019");
+        assert_eq!(&info.get(b"Subject").unwrap().as_str().unwrap()[..2], &[0xFE, 0xFF]);
+
+        let catalog = doc.catalog().unwrap();
+        let xmp_id = catalog.get(b"Metadata").unwrap().as_reference().unwrap();
+        let xmp = doc.get_object(xmp_id).unwrap().as_stream().unwrap();
+        let xmp = String::from_utf8(xmp.content.clone()).unwrap();
+        assert!(xmp.contains("<dc:publisher><rdf:Bag><rdf:li>Time Inc &amp; Co</rdf:li></rdf:Bag></dc:publisher>"));
+        assert!(xmp.contains("<prism:volume>67</prism:volume>"));
+        assert!(xmp.contains("<prism:number>14</prism:number>"));
+        assert!(xmp.contains("<prism:coverDate>1969-10-03</prism:coverDate>"));
+    }
+
+    #[test]
+    fn nested_bookmarks() {
+        let dir = fixtures::TempDir::new("pdf-bookmarks");
+        for name in ["0001.png", "0002.png", "0003.png"] {
+            image::RgbImage::new(4, 6).save(dir.join(name)).unwrap();
+        }
+
+        let mut toc = TableOfContents::new();
+        toc.add_page("Part I", "0001.png");
+        toc.add_page_at_level("Chapter 1", "0001.png", 1);
+        toc.add_page_at_level("Missing", "9999.png", 1);
+        toc.add_page_at_level("Section 1.1", "0002.png", 2);
+        toc.add_page("Part II", "0003.png");
+        assert_eq!(toc.page_title("0001.png"), Some("Part I"));
+        assert_eq!(toc.page_title("0002.png"), Some("Section 1.1"));
+
+        let target = dir.join("out.pdf");
+        create_pdf_with_toc(dir.to_str().unwrap(), target.to_str().unwrap(), &toc).unwrap();
+
+        let doc = Document::load(&target).unwrap();
+        let title = |dict: &lopdf::Dictionary| dict.get(b"Title").unwrap().as_str().unwrap().to_vec();
+        let child = |dict: &lopdf::Dictionary, key: &[u8]| {
+            doc.get_dictionary(dict.get(key).unwrap().as_reference().unwrap()).unwrap()
+        };
+        let outlines = child(doc.catalog().unwrap(), b"Outlines");
+        let part_1 = child(outlines, b"First");
+        assert_eq!(title(part_1), b"Part I");
+        assert_eq!(title(child(outlines, b"Last")), b"Part II");
+        let chapter_1 = child(part_1, b"First");
+        assert_eq!(title(chapter_1), b"Chapter 1");
+        // Entry for missing image is skipped, with its child attached to the entry above it instead.
+        assert_eq!(title(child(part_1, b"Last")), b"Section 1.1");
+    }
+
+    #[test]
+    fn page_labels_from_filenames() {
+        let filenames = [
+            "00001-PP1.png",
+            "00002-PR3.png",
+            "00003-PR4.png",
+            "00004-PA1.png",
+            "00005-PA2.png",
+            "00006-PA5.png",
+            "cover.png",
+        ];
+        let labels: Vec<_> = filenames.iter().map(|x| PageLabel::from_filename(x)).collect();
+        let nums = page_labels(&labels).unwrap();
+        let nums = nums.get(b"Nums").unwrap().as_array().unwrap();
+        let ranges: Vec<_> = nums
+            .chunks(2)
+            .map(|x| {
+                let dict = x[1].as_dict().unwrap();
+                let get = |key: &[u8]| dict.get(key).ok().cloned();
+                (x[0].as_i64().unwrap(), get(b"S"), get(b"St"), get(b"P"))
+            })
+            .collect();
+        let name = |x: &str| Some(Object::Name(x.as_bytes().to_vec()));
+        let int = |x: i64| Some(Object::Integer(x));
+        assert_eq!(
+            ranges,
+            [
+                (0, None, None, Some(Object::string_literal("PP1"))),
+                (1, name("r"), int(3), None),
+                (3, name("D"), int(1), None),
+                (5, name("D"), int(5), None),
+                (6, name("D"), int(7), None),
+            ]
+        );
+
+        // No labels if nothing has a printed page number.
+        let labels = vec![PageLabel::from_filename("0001.png"), PageLabel::from_filename("00002-PP1.png")];
+        assert!(page_labels(&labels).is_none());
+    }
+
+    #[test]
+    fn link_annotations() {
+        let dir = fixtures::TempDir::new("pdf-links");
+        for name in ["0001.png", "0002.png"] {
+            image::RgbImage::new(40, 60).save(dir.join(name)).unwrap();
+        }
+
+        let mut links = PageLinks::new();
+        let link = |rect, target| PageLink { rect, target };
+        links.add_link("0001.png", link([10., 5., 20., 10.], LinkTarget::Page("0002.png".to_string())));
+        links.add_link("0001.png", link([0., 0., 5., 5.], LinkTarget::Uri("https://example.com/".to_string())));
+        links.add_link("0001.png", link([0., 0., 5., 5.], LinkTarget::Page("9999.png".to_string())));
+        let target = dir.join("out.pdf");
+        let options = PdfOptions {
+            links: Some(&links),
+            ..Default::default()
+        };
+        create_pdf_with_options(dir.to_str().unwrap(), target.to_str().unwrap(), &options).unwrap();
+
+        let doc = Document::load(&target).unwrap();
+        let pages = doc.get_pages();
+        let page = doc.get_dictionary(pages[&1]).unwrap();
+        let annots = page.get(b"Annots").unwrap().as_array().unwrap();
+        assert_eq!(annots.len(), 2);
+
+        let annot = doc.get_dictionary(annots[0].as_reference().unwrap()).unwrap();
+        let rect: Vec<f32> = annot
+            .get(b"Rect")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x.as_float().unwrap())
+            .collect();
+        assert_eq!(rect, [10., 45., 30., 55.]);
+        let dest = annot.get(b"Dest").unwrap().as_array().unwrap();
+        assert_eq!(dest[0].as_reference().unwrap(), pages[&2]);
+
+        let annot = doc.get_dictionary(annots[1].as_reference().unwrap()).unwrap();
+        let action = annot.get(b"A").unwrap().as_dict().unwrap();
+        assert_eq!(action.get(b"URI").unwrap().as_str().unwrap(), b"https://example.com/");
+
+        let page = doc.get_dictionary(pages[&2]).unwrap();
+        assert!(page.get(b"Annots").is_err());
+    }
+
+    #[test]
+    fn invisible_text_layer() {
+        let dir = fixtures::TempDir::new("pdf-text");
+        for name in ["0001.png", "0002.png"] {
+            image::RgbImage::new(100, 200).save(dir.join(name)).unwrap();
+        }
+        std::fs::write(
+            dir.join("0001.hocr"),
+            r#"<span class="ocrx_word" title="bbox 10 20 70 40">Whale</span>"#,
+        )
+        .unwrap();
+
+        let texts = PageTexts::from_image_dir(dir.to_str().unwrap()).unwrap();
+        let target = dir.join("out.pdf");
+        let options = PdfOptions {
+            text: Some(&texts),
+            ..Default::default()
+        };
+        create_pdf_with_options(dir.to_str().unwrap(), target.to_str().unwrap(), &options).unwrap();
+
+        let doc = Document::load(&target).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(doc.extract_text(&[1]).unwrap().trim(), "Whale");
+        let content = doc.get_and_decode_page_content(pages[&1]).unwrap();
+        let operation = |name: &str| content.operations.iter().find(|x| x.operator == name).unwrap();
+        assert_eq!(operation("Tr").operands[0].as_i64().unwrap(), 3);
+        let tm: Vec<f32> = operation("Tm").operands.iter().map(|x| x.as_float().unwrap()).collect();
+        assert_eq!(tm, [1., 0., 0., 1., 10., 164.]);
+        // Image is still drawn alongside the text.
+        assert!(content.operations.iter().any(|x| x.operator == "Do"));
+
+        let content = doc.get_and_decode_page_content(pages[&2]).unwrap();
+        assert!(!content.operations.iter().any(|x| x.operator == "Tj"));
+    }
+}
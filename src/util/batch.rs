@@ -0,0 +1,241 @@
+//! Lists of books to download read from a file, each with its own options.
+
+use clap::Parser;
+use gbscraper::scraper::{IssueFilter, PublishDate, ScraperOptions};
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+
+use crate::{parse_date, resolve_tld, DownloadMode, Format};
+
+/// Book or periodical to download, with options overriding those given on the command line.
+///
+/// In text files each line is a URL or ID followed by any of these options, e.g. `<URL> -m full --after 1950`.
+#[derive(Parser, Deserialize, Debug, Default, Clone, PartialEq)]
+#[command(no_binary_name = true)]
+#[serde(deny_unknown_fields)]
+pub struct BatchEntry {
+    /// URL or ID of book.
+    #[arg(value_name = "URL")]
+    #[serde(alias = "id")]
+    pub url: String,
+
+    /// Which issues to download from URL.
+    #[arg(value_enum, short = 'm', long = "download-mode", value_name = "MODE")]
+    #[serde(default, alias = "download_mode")]
+    pub mode: Option<DownloadMode>,
+
+    /// Directory to save issue(s) to.
+    #[arg(short = 'o', long = "target-dir", value_name = "DIRECTORY")]
+    #[serde(default)]
+    pub target_dir: Option<String>,
+
+    /// Format(s) to convert downloaded images to.
+    #[arg(value_enum, short, long, value_delimiter = ',', num_args = 1..)]
+    #[serde(default, alias = "formats")]
+    pub format: Option<Vec<Format>>,
+
+    /// Only download issues published on or after this date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub after: Option<PublishDate>,
+
+    /// Only download issues published on or before this date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub before: Option<PublishDate>,
+
+    /// Only download issues whose full title contains this text, ignoring case.
+    #[arg(long = "match-title", value_name = "TEXT")]
+    #[serde(default)]
+    pub match_title: Option<String>,
+
+    /// Maximum number of issues to download in period and full download modes.
+    #[arg(long, value_name = "N")]
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Entry in a TOML or JSON batch file, which may be just a URL or ID.
+struct RawEntry(BatchEntry);
+
+/// Contents of a TOML or JSON batch file with entries under `entries`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchFile {
+    entries: Vec<RawEntry>,
+}
+
+impl<'de> Deserialize<'de> for RawEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = RawEntry;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a URL or ID, or a table with a url and options")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(RawEntry(BatchEntry {
+                    url: v.to_string(),
+                    ..Default::default()
+                }))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                BatchEntry::deserialize(de::value::MapAccessDeserializer::new(map)).map(RawEntry)
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+impl BatchEntry {
+    /// Gets the URL of the entry, building one if only an ID was given.
+    pub fn book_url(&self) -> String {
+        match self.url.contains("://") {
+            true => self.url.clone(),
+            false => format!("https://books.google.com/books?id={}", self.url.trim()),
+        }
+    }
+
+    /// Applies the options of the entry on top of those given on the command line.
+    pub fn apply(&self, base: &ScraperOptions, tld_override: Option<&str>) -> ScraperOptions {
+        let mut options = base.clone();
+        options.tld = resolve_tld(tld_override, &self.book_url());
+        if self.format.is_some() {
+            options.formats = Format::to_flags(self.format.as_deref());
+        }
+        options.filter = IssueFilter {
+            after: self.after.or(base.filter.after),
+            before: self.before.or(base.filter.before),
+            title: self.match_title.clone().or_else(|| base.filter.title.clone()),
+            limit: self.limit.or(base.filter.limit),
+        };
+        options
+    }
+}
+
+/// Reads a batch file as TOML or JSON if it has that extension, or otherwise as text with one entry per line.
+///
+/// TOML and JSON files hold a list of entries under `entries`, or at the top level of JSON files. Each
+/// entry is a URL or ID, or a table with a `url` and any options. Blank lines and lines starting with `#`
+/// are ignored in text files.
+pub fn load(path: &str) -> Result<Vec<BatchEntry>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {path}: {e}"))?;
+    let extension = Path::new(path).extension().and_then(|x| x.to_str()).map(|x| x.to_lowercase());
+    let entries = match extension.as_deref() {
+        Some("toml") => toml::from_str::<BatchFile>(&text).map(|x| x.entries).map_err(|e| e.to_string()),
+        Some("json") if text.trim_start().starts_with('[') => {
+            serde_json::from_str::<Vec<RawEntry>>(&text).map_err(|e| e.to_string())
+        }
+        Some("json") => serde_json::from_str::<BatchFile>(&text).map(|x| x.entries).map_err(|e| e.to_string()),
+        _ => return parse_text(&text).map_err(|e| format!("{path}: {e}")),
+    };
+    Ok(entries.map_err(|e| format!("{path}: {e}"))?.into_iter().map(|x| x.0).collect())
+}
+
+/// Parses a text batch file with one entry and its options per line.
+fn parse_text(text: &str) -> Result<Vec<BatchEntry>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", i + 1, e.trim());
+        // Only keep the first line of errors, leaving out usage that refers to the command line.
+        let entry = BatchEntry::try_parse_from(split_words(line).map_err(error)?).map_err(|e| {
+            let message = e.to_string();
+            let first_line = message.lines().next().unwrap_or_default();
+            error(first_line.trim_start_matches("error: ").to_string())
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Splits a line into words separated by whitespace, allowing words to be quoted with `"` or `'`.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unmatched quote".to_string());
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Deserializes an optional date given as YYYY-MM-DD, YYYY-MM or YYYY.
+fn deserialize_date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PublishDate>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|x| parse_date(&x).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_entries() {
+        let entries = parse_text(
+            "# LIFE in the fifties\n\
+             https://books.google.com/books?id=CFEEAAAAMBAJ -m full --after 1950 --before 1959 -o 'LIFE 1950s'\n\
+             \n\
+             XV8XAAAAYAAJ -f pdf,cbz\n",
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].mode, Some(DownloadMode::Full));
+        assert_eq!(entries[0].target_dir.as_deref(), Some("LIFE 1950s"));
+        assert_eq!(entries[0].before, PublishDate::parse_iso("1959"));
+        assert_eq!(entries[1].book_url(), "https://books.google.com/books?id=XV8XAAAAYAAJ");
+        assert_eq!(entries[1].format, Some(vec![Format::Pdf, Format::Cbz]));
+
+        let error = parse_text("XV8XAAAAYAAJ --after 1950-13").unwrap_err();
+        assert!(error.starts_with("line 1:"), "{error}");
+    }
+
+    #[test]
+    fn toml_and_json_entries() {
+        let toml = r#"
+            [[entries]]
+            url = "https://books.google.com/books?id=CFEEAAAAMBAJ"
+            mode = "full"
+            after = "1950-01-01"
+            limit = 10
+
+            [[entries]]
+            id = "XV8XAAAAYAAJ"
+            formats = ["epub"]
+        "#;
+        let entries: Vec<_> = toml::from_str::<BatchFile>(toml).unwrap().entries.into_iter().map(|x| x.0).collect();
+        assert_eq!(entries[0].mode, Some(DownloadMode::Full));
+        assert_eq!(entries[0].after, PublishDate::parse_iso("1950-01-01"));
+        assert_eq!(entries[0].limit, Some(10));
+        assert_eq!(entries[1].url, "XV8XAAAAYAAJ");
+        assert_eq!(entries[1].format, Some(vec![Format::Epub]));
+
+        let json = r#"["XV8XAAAAYAAJ", {"url": "CFEEAAAAMBAJ", "target_dir": "LIFE"}]"#;
+        let entries: Vec<_> = serde_json::from_str::<Vec<RawEntry>>(json).unwrap().into_iter().map(|x| x.0).collect();
+        assert_eq!(entries[0].url, "XV8XAAAAYAAJ");
+        assert_eq!(entries[1].target_dir.as_deref(), Some("LIFE"));
+    }
+}
@@ -1,23 +1,51 @@
-use clap::{Parser, ValueEnum};
+mod batch;
+
+use clap::{Parser, Subcommand, ValueEnum};
 use gbscraper::*;
+use serde::Deserialize;
 use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
 use crate::scraper::FALLBACK_TLD;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// URL of book to download.
-    #[arg(value_name = "URL")] //(short = 'i', long, value_name = "BOOK_URL")]
-    url: String,
+    #[arg(value_name = "URL", required_unless_present_any = ["batch_file", "retry_failed"])] //(short = 'i', long, value_name = "BOOK_URL")]
+    url: Option<String>,
+
+    /// File listing books to download in place of URL, as text with a URL or ID and any options per line, or as TOML or JSON.
+    #[arg(short = 'b', long = "batch-file", value_name = "FILE", conflicts_with = "url")]
+    batch_file: Option<String>,
+
+    /// If set, books whose latest download failed according to the archive file will be downloaded again in place of URL.
+    #[arg(long = "retry-failed", default_value_t = false, requires = "archive", conflicts_with_all = ["url", "batch_file"])]
+    retry_failed: bool,
+
+    /// Which issues to download from URL.
+    #[arg(value_enum, short = 'm', long = "download-mode", value_name = "MODE", default_value_t = DownloadMode::Single)]
+    download_mode: DownloadMode,
+
+    #[command(flatten)]
+    download: DownloadArgs,
+}
 
-    /// Directory to save issue(s) to.
-    #[arg(
-        short = 'o',
-        long = "target-dir",
-        value_name = "DIRECTORY",
-        default_value = "."
-    )]
-    target_dir: String,
+/// Options for downloading books, shared by the main command and `sync`.
+#[derive(clap::Args, Debug)]
+struct DownloadArgs {
+    /// Directory to save issue(s) to. If omitted, the current directory is used.
+    #[arg(short = 'o', long = "target-dir", value_name = "DIRECTORY")]
+    target_dir: Option<String>,
 
     /// If set, downloaded images will not be deleted after conversion.
     #[arg(short, long = "keep-images", default_value_t = false)]
@@ -27,11 +55,7 @@ struct Args {
     #[arg(value_enum, short, long, value_delimiter = ',', num_args = 1.., default_value ="pdf")]
     format: Option<Vec<Format>>,
 
-    /// Which issues to download from URL.
-    #[arg(value_enum, short = 'm', long = "download-mode", value_name = "MODE", default_value_t = DownloadMode::Single)]
-    download_mode: DownloadMode,
-
-    /// Omit previously downloaded books referenced in provided file. If provided, newly downloaded books will be automatically added to file.
+    /// Omit previously downloaded books recorded in provided file. If provided, newly downloaded books will be automatically added to file, along with their titles, output files and completion times, as will failed downloads.
     #[arg(short, long)]
     archive: Option<String>,
 
@@ -39,6 +63,26 @@ struct Args {
     #[arg(short, long, short = 'r', default_value_t = 3)]
     download_attempts: u32,
 
+    /// Maximum number of pages to download at once. Higher values are faster but more likely to be flagged as unusual traffic.
+    #[arg(short = 'j', long, value_name = "PAGES", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    concurrency: u32,
+
+    /// Maximum number of requests to make per second. Set to 0 for no limit.
+    #[arg(long = "rate-limit", value_name = "REQUESTS_PER_SECOND", default_value_t = 0.)]
+    rate_limit: f64,
+
+    /// Minimum time to wait between requests, in milliseconds.
+    #[arg(long, value_name = "MILLISECONDS", default_value_t = 0)]
+    delay: u64,
+
+    /// Maximum random time to add to the wait between requests, in milliseconds.
+    #[arg(long, value_name = "MILLISECONDS", default_value_t = 0)]
+    jitter: u64,
+
+    /// If set, the text Google provides for each page will be downloaded and added to PDFs as an invisible, searchable layer.
+    #[arg(long = "ocr-text", default_value_t = false)]
+    ocr_text: bool,
+
     /// If set, extra output will be given.
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
@@ -47,76 +91,484 @@ struct Args {
     /// Set to "none" to disable URL normalization and use TLD from provided URL.
     #[arg(short, long)]
     tld_override: Option<String>,
-    
-    // TODO: File naming scheme
+
+    /// Only download issues published on or after this date, given as YYYY-MM-DD, YYYY-MM or YYYY.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    after: Option<scraper::PublishDate>,
+
+    /// Only download issues published on or before this date, given as YYYY-MM-DD, YYYY-MM or YYYY.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    before: Option<scraper::PublishDate>,
+
+    /// Only download issues whose full title, including the publish date of periodicals, contains this text, ignoring case.
+    #[arg(long = "match-title", value_name = "TEXT")]
+    match_title: Option<String>,
+
+    /// Maximum number of issues to download in period and full download modes. Issues already downloaded are not counted.
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+
+    /// Template for the path of each book within the target directory, e.g. "{title}/{year}-{month}-{day}".
+    /// Available fields: title, full_title, publish_date, year, month, day, volume, issn, author, publisher, id.
+    /// If omitted, books are saved as "{full_title} [{id}]" and issues as "{title}/{full_title} [{id}]".
+    #[arg(short = 'n', long = "name-template", value_name = "TEMPLATE")]
+    name_template: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert a directory of images kept from a previous download without downloading anything.
+    Convert(ConvertArgs),
+    /// Show the metadata, viewable pages and table of contents of a book without downloading it.
+    Info(InfoArgs),
+    /// List the issues of a magazine or newspaper without downloading them.
+    List(ListArgs),
+    /// Download new issues of subscribed magazines and newspapers, or edit subscriptions.
+    Sync(SyncArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    /// Directory of page images, e.g. one kept with --keep-images.
+    #[arg(value_name = "IMAGE_DIR")]
+    image_dir: String,
+
+    /// Path of output file(s) without an extension. If omitted, files are saved beside the image directory with the same name.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+
+    /// Format(s) to convert images to. Existing files are replaced.
+    #[arg(value_enum, short, long, value_delimiter = ',', num_args = 1.., default_value = "pdf")]
+    format: Option<Vec<Format>>,
+
+    /// Manifest recording the pages, table of contents and links of the book. If omitted, the manifest in the image directory is used if there is one.
+    #[arg(short, long, value_name = "FILE")]
+    manifest: Option<String>,
+
+    /// JSON list of table of contents entries, e.g. [{"page_id": "PA1", "title": "Chapter 1", "level": 0}], replacing any in the manifest.
+    #[arg(short, long, value_name = "FILE")]
+    toc: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct InfoArgs {
+    /// URL of book.
+    #[arg(value_name = "URL")]
+    url: String,
+
+    /// If set, information will be printed as JSON.
+    #[arg(long, default_value_t = false)]
+    json: bool,
+
+    /// If set, extra output will be given.
+    #[arg(short, long, default_value_t = false)]
+    verbose: bool,
+
+    /// The top level domain to normalize URLs to. If omitted, ".us" will be used.
+    /// Set to "none" to disable URL normalization and use TLD from provided URL.
+    #[arg(short, long)]
+    tld_override: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ListArgs {
+    /// URL of any issue of the magazine or newspaper.
+    #[arg(value_name = "URL")]
+    url: String,
+
+    /// Which issues to list from URL.
+    #[arg(value_enum, short = 'm', long = "list-mode", value_name = "MODE", default_value_t = ListMode::Full)]
+    list_mode: ListMode,
+
+    /// Format to print issues in.
+    #[arg(value_enum, short, long, default_value_t = ListFormat::Table)]
+    format: ListFormat,
+
+    /// If set, the metadata of each issue will be fetched, adding volumes and exact publish dates at the cost of one request per issue.
+    #[arg(long, default_value_t = false)]
+    metadata: bool,
+
+    /// If set, extra output will be given.
+    #[arg(short, long, default_value_t = false)]
+    verbose: bool,
+
+    /// The top level domain to normalize URLs to. If omitted, ".us" will be used.
+    /// Set to "none" to disable URL normalization and use TLD from provided URL.
+    #[arg(short, long)]
+    tld_override: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct SyncArgs {
+    /// File recording subscribed series and how far each has been synced.
+    #[arg(short, long, value_name = "FILE", default_value = "subscriptions.json")]
+    subscriptions: String,
+
+    /// Subscribe to the series of the issue at URL, saving its issues to the target directory if given, instead of syncing.
+    #[arg(long, value_name = "URL", conflicts_with = "remove")]
+    add: Option<String>,
+
+    /// Unsubscribe from the series with the provided URL or ID instead of syncing.
+    #[arg(long, value_name = "URL")]
+    remove: Option<String>,
+
+    /// If set, keep running and sync again after this many minutes.
+    #[arg(short, long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+    interval: Option<u64>,
+
+    #[command(flatten)]
+    download: DownloadArgs,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ListMode {
+    Period,
+    Full,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ListFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum Format {
     None,
     Pdf,
     Cbz,
+    Epub,
     All,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum DownloadMode {
     Single,
     Period,
     Full,
 }
 
-impl Args {
-    /// Converts command line options to options for scraper methods
-    fn to_options(&self) -> std::io::Result<scraper::ScraperOptions> {
-        Ok(scraper::ScraperOptions {
-            keep_images: self.keep_images,
-            formats: {
-                match self.format.as_ref() {
-                    None => scraper::FormatFlags::Pdf,
-                    Some(v) => {
-                        let mut flags = scraper::FormatFlags::None;
-                        for f in v {
-                            flags |= match f {
-                                Format::None => scraper::FormatFlags::None,
-                                Format::Pdf => scraper::FormatFlags::Pdf,
-                                Format::Cbz => scraper::FormatFlags::Cbz,
-                                Format::All => scraper::FormatFlags::All,
-                            }
-                        }
-                        flags
+impl Format {
+    /// Combines formats into flags, defaulting to PDF if none are given.
+    fn to_flags(formats: Option<&[Format]>) -> scraper::FormatFlags {
+        match formats {
+            None => scraper::FormatFlags::Pdf,
+            Some(v) => {
+                let mut flags = scraper::FormatFlags::None;
+                for f in v {
+                    flags |= match f {
+                        Format::None => scraper::FormatFlags::None,
+                        Format::Pdf => scraper::FormatFlags::Pdf,
+                        Format::Cbz => scraper::FormatFlags::Cbz,
+                        Format::Epub => scraper::FormatFlags::Epub,
+                        Format::All => scraper::FormatFlags::All,
                     }
                 }
+                flags
+            }
+        }
+    }
+}
+
+/// Parses a date given on the command line.
+fn parse_date(text: &str) -> std::result::Result<scraper::PublishDate, String> {
+    scraper::PublishDate::parse_iso(text).ok_or_else(|| format!("expected YYYY-MM-DD, YYYY-MM or YYYY, got \"{text}\""))
+}
+
+/// Gets the top level domain to normalize URLs to from the command line override, if any.
+fn resolve_tld(tld_override: Option<&str>, url: &str) -> String {
+    match tld_override {
+        // None, provided, use default
+        None => FALLBACK_TLD.to_string(),
+        Some(tld) => match tld.to_lowercase().as_str() {
+            // Provided "none", disable normalization and use TLD from URL.
+            "none" => match tldextract::TldExtractor::new(tldextract::TldOption::default()).extract(url) {
+                Ok(x) => match x.suffix {
+                    Some(x) => format!(".{x}"),
+                    None => FALLBACK_TLD.to_string(),
+                },
+                Err(_) => FALLBACK_TLD.to_string(),
+            },
+            // Provided TLD, use it.
+            _ => tld.to_string(),
+        },
+    }
+}
+
+impl ConvertArgs {
+    /// Converts image directory to requested formats using its manifest and TOC file, if provided.
+    fn run(&self) -> gbscraper::error::Result<()> {
+        let image_dir = self.image_dir.trim_end_matches(['/', '\\']);
+        let mut manifest = match self.manifest.as_ref() {
+            Some(file) => serde_json::from_str(&std::fs::read_to_string(file)?)?,
+            None => match scraper::Manifest::load(image_dir)? {
+                Some(x) => x,
+                None => scraper::Manifest::from_image_dir("", image_dir)?,
             },
+        };
+        if let Some(file) = self.toc.as_ref() {
+            let toc: Vec<scraper::ManifestTocEntry> = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+            manifest.update_toc(&toc);
+        }
+        let output = self.output.as_deref().unwrap_or(image_dir);
+        if let Some(parent) = std::path::Path::new(output).parent().filter(|x| !x.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        scraper::convert_image_dir(
+            image_dir,
+            output,
+            Format::to_flags(self.format.as_deref()),
+            &manifest,
+            manifest.metadata.as_ref(),
+        )
+    }
+}
+
+impl InfoArgs {
+    /// Fetches information about book and prints it.
+    fn run(&self) -> gbscraper::error::Result<()> {
+        let options = scraper::ScraperOptions {
+            verbose: self.verbose,
+            tld: resolve_tld(self.tld_override.as_deref(), &self.url),
+            ..Default::default()
+        };
+        let info = scraper::get_book_info(&self.url, &options)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            return Ok(());
+        }
+
+        let meta = &info.metadata;
+        let fields = [
+            ("Title", meta.get_full_title()),
+            ("ID", meta.id.clone()),
+            ("Type", format!("{:?}", meta.book_type)),
+            ("Author", meta.author.clone()),
+            ("Publisher", meta.publisher.clone()),
+            ("Published", meta.publish_date.clone()),
+            ("Volume", meta.volume.clone()),
+            ("ISSN", meta.issn.clone()),
+            ("Length", format!("{} pages", meta.length)),
+            ("Viewable", format!("{} pages", info.available_pages.len())),
+            ("Original from", meta.orig_from.clone()),
+            ("Digitized", meta.date_digitized.clone()),
+            ("URL", info.url.clone()),
+        ];
+        for (label, value) in fields.iter().filter(|(_, value)| !value.is_empty()) {
+            println!("{label:<14}{value}");
+        }
+        if !meta.description.is_empty() {
+            println!("\n{}", meta.description);
+        }
+        if !info.toc.is_empty() {
+            println!("\nContents:");
+            for entry in &info.toc {
+                println!("{:indent$}{} ({})", "", entry.title, entry.page_id, indent = 2 * (entry.level + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ListArgs {
+    /// Finds issues and prints them in the requested format.
+    fn run(&self) -> gbscraper::error::Result<()> {
+        let options = scraper::ScraperOptions {
+            verbose: self.verbose,
+            tld: resolve_tld(self.tld_override.as_deref(), &self.url),
+            ..Default::default()
+        };
+        let mut issues = match self.list_mode {
+            ListMode::Period => scraper::list_period(&self.url, &options)?,
+            ListMode::Full => scraper::list_all(&self.url, &options)?,
+        };
+        if self.metadata {
+            for issue in issues.iter_mut() {
+                match scraper::get_book_metadata(&issue.url, &options) {
+                    Ok(meta) => issue.metadata = Some(meta),
+                    Err(x) => eprintln!("Error fetching metadata of issue {}: {}", issue.id, x),
+                }
+            }
+        }
+
+        if self.format == ListFormat::Json {
+            println!("{}", serde_json::to_string_pretty(&issues)?);
+            return Ok(());
+        }
+        let header = ["ID", "Date", "Volume", "URL"];
+        let rows: Vec<[&str; 4]> = issues
+            .iter()
+            .map(|x| match &x.metadata {
+                Some(meta) => [x.id.as_str(), meta.publish_date.as_str(), meta.volume.as_str(), x.url.as_str()],
+                None => [x.id.as_str(), x.caption.as_str(), "", x.url.as_str()],
+            })
+            .collect();
+        match self.format {
+            ListFormat::Csv => {
+                for row in std::iter::once(header).chain(rows) {
+                    println!("{}", row.map(csv_field).join(","));
+                }
+            }
+            _ => {
+                let mut widths = header.map(|x| x.chars().count());
+                for row in &rows {
+                    for (width, value) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(value.chars().count());
+                    }
+                }
+                for row in std::iter::once(header).chain(rows) {
+                    let cells: Vec<_> = row.iter().zip(widths).map(|(x, w)| format!("{x:<w$}")).collect();
+                    println!("{}", cells.join("  ").trim_end());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SyncArgs {
+    /// Adds or removes a subscription, or syncs every subscribed series once or repeatedly.
+    fn run(&self) -> gbscraper::error::Result<()> {
+        if let Some(url) = self.add.as_ref() {
+            // Store an absolute path, as later syncs may run from another directory.
+            let target_dir = match self.download.target_dir.as_ref() {
+                Some(x) => Some(std::path::absolute(x)?.to_string_lossy().to_string()),
+                None => None,
+            };
+            let mut subscriptions = scraper::Subscriptions::load(&self.subscriptions)?;
+            match subscriptions.add(url, target_dir.as_deref())? {
+                true => println!("Subscribed to {url}."),
+                false => println!("Already subscribed to {url}."),
+            }
+            return subscriptions.save(&self.subscriptions);
+        }
+        if let Some(url) = self.remove.as_ref() {
+            let mut subscriptions = scraper::Subscriptions::load(&self.subscriptions)?;
+            match subscriptions.remove(url) {
+                true => println!("Unsubscribed from {url}."),
+                false => println!("Not subscribed to {url}."),
+            }
+            return subscriptions.save(&self.subscriptions);
+        }
+
+        let options = self.download.to_options("")?;
+        loop {
+            self.sync_all(&options)?;
+            let Some(minutes) = self.interval else {
+                return Ok(());
+            };
+            println!("Syncing again in {minutes} minute(s)...");
+            std::thread::sleep(Duration::from_secs(minutes * 60));
+        }
+    }
+
+    /// Downloads new issues of every subscribed series, recording progress after each.
+    fn sync_all(&self, options: &scraper::ScraperOptions) -> gbscraper::error::Result<()> {
+        let subscriptions = scraper::Subscriptions::load(&self.subscriptions)?;
+        if subscriptions.series.is_empty() {
+            println!("No subscriptions in {}. Add one with --add <URL>.", self.subscriptions);
+            return Ok(());
+        }
+        let mut already_downloaded = HashSet::<String>::new();
+        if let Some(file) = self.download.archive.as_ref() {
+            already_downloaded.extend(scraper::Archive::open(file)?.downloaded_ids().map(String::from));
+        }
+
+        for (i, subscription) in subscriptions.series.iter().enumerate() {
+            println!("[{}/{}] {}", i + 1, subscriptions.series.len(), subscription.url);
+            let mut subscription = subscription.clone();
+            let options = scraper::ScraperOptions {
+                tld: resolve_tld(self.download.tld_override.as_deref(), &subscription.url),
+                ..options.clone()
+            };
+            let target_dir = subscription.target_dir.clone();
+            let target_dir = target_dir.as_deref().unwrap_or(self.download.target_dir());
+            match scraper::sync_series(&mut subscription, target_dir, &options, &mut already_downloaded) {
+                Ok(n) => println!("Downloaded {n} new issue(s)."),
+                Err(x) => eprintln!("Error syncing {}: {}", subscription.url, x),
+            }
+
+            // Reload before saving so subscriptions edited while syncing are kept.
+            let mut latest = scraper::Subscriptions::load(&self.subscriptions)?;
+            latest.update(&subscription);
+            latest.save(&self.subscriptions)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes a value for a CSV file if needed.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl DownloadArgs {
+    /// Gets the directory to save issues to.
+    fn target_dir(&self) -> &str {
+        self.target_dir.as_deref().unwrap_or(".")
+    }
+
+    /// Converts command line options to options for scraper methods
+    fn to_options(&self, url: &str) -> std::io::Result<scraper::ScraperOptions> {
+        Ok(scraper::ScraperOptions {
+            keep_images: self.keep_images,
+            formats: Format::to_flags(self.format.as_deref()),
             archive_file: self.archive.clone(),
             skip_download: false,
             download_attempts: self.download_attempts,
+            concurrency: self.concurrency as usize,
+            rate_limiter: Arc::new(scraper::RateLimiter::new(
+                scraper::RateLimiter::interval_for_rate(self.rate_limit)
+                    .max(Duration::from_millis(self.delay)),
+                Duration::from_millis(self.jitter),
+            )),
+            ocr_text: self.ocr_text,
             verbose: self.verbose,
-            tld: match &self.tld_override {
-                // None, provided, use default
-                None => FALLBACK_TLD.to_string(),
-                Some(tld) => match tld.to_lowercase().as_str() {
-                    // Provided "none", disable normalization and use TLD from URL.
-                    "none" => match tldextract::TldExtractor::new(tldextract::TldOption::default())
-                        .extract(&self.url)
-                    {
-                        Ok(x) => match x.suffix {
-                            Some(x) => format!(".{x}"),
-                            None => FALLBACK_TLD.to_string(),
-                        },
-                        Err(_) => FALLBACK_TLD.to_string(),
-                    },
-                    // Provided TLD, use it.
-                    _ => tld.to_string(),
-                },
+            tld: resolve_tld(self.tld_override.as_deref(), url),
+            name_template: self
+                .name_template
+                .as_deref()
+                .map(scraper::NameTemplate::parse)
+                .transpose()
+                .map_err(std::io::Error::from)?,
+            filter: scraper::IssueFilter {
+                after: self.after,
+                before: self.before,
+                title: self.match_title.clone(),
+                limit: self.limit,
             },
+            ..Default::default()
         })
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
-    let options = match args.to_options() {
+    let result = match &args.command {
+        Some(Command::Convert(convert)) => convert.run(),
+        Some(Command::Info(info)) => info.run(),
+        Some(Command::List(list)) => list.run(),
+        Some(Command::Sync(sync)) => sync.run(),
+        None => return download(&args),
+    };
+    if let Err(x) = result {
+        eprintln!("Error: {}", x);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Downloads the book or issues at the URL provided on the command line, those listed in the batch file, or those
+/// that failed before.
+fn download(args: &Args) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let options = match args.download.to_options(args.url.as_deref().unwrap_or_default()) {
         Ok(opts) => opts,
         Err(e) => {
             eprintln!("Error: {}", e);
@@ -124,39 +576,80 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
     let mut already_downloaded = HashSet::<String>::new();
-    if let Some(file) = args.archive.as_ref() {
-        if std::fs::exists(file)? {
-            for line in std::fs::read_to_string(file)?.lines() {
-                let trimmed = line.trim();
-                if !trimmed.is_empty() {
-                    already_downloaded.insert(trimmed.to_string());
-                }
+    let mut failed_entries = Vec::new();
+    if let Some(file) = args.download.archive.as_ref() {
+        let archive = scraper::Archive::open(file)?;
+        already_downloaded.extend(archive.downloaded_ids().map(String::from));
+        failed_entries.extend(archive.failed().cloned());
+    }
+
+    let entries = if let Some(batch_file) = args.batch_file.as_ref() {
+        match batch::load(batch_file) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
             }
         }
-    }
-    let result = match args.download_mode {
-        DownloadMode::Single => scraper::download_issue_skip_downloaded(
-            &args.url,
-            &args.target_dir,
-            &options,
-            Some(&mut already_downloaded),
-        )
-        .map(|_| ()),
-        DownloadMode::Period => scraper::download_period(
-            &args.url,
-            &args.target_dir,
-            &options,
-            &mut already_downloaded,
-        ),
-        DownloadMode::Full => scraper::download_all(
-            &args.url,
-            &args.target_dir,
-            &options,
-            &mut already_downloaded,
-        ),
+    } else if args.retry_failed {
+        // Retry each failed book on its own, where it was originally being saved.
+        failed_entries
+            .into_iter()
+            .map(|x| batch::BatchEntry {
+                url: x.url.unwrap_or(x.id),
+                mode: Some(DownloadMode::Single),
+                target_dir: x.target_dir,
+                ..Default::default()
+            })
+            .collect()
+    } else {
+        let url = args.url.as_deref().unwrap_or_default();
+        if let Err(x) = download_url(args.download_mode, url, args.download.target_dir(), &options, &mut already_downloaded) {
+            eprintln!("Scraper error: {}", x);
+        }
+        return Ok(());
     };
-    if let Err(x) = result {
-        eprintln!("Scraper error: {}", x);
+    let mut failed = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let url = entry.book_url();
+        println!("[{}/{}] {url}", i + 1, entries.len());
+        let entry_options = entry.apply(&options, args.download.tld_override.as_deref());
+        let mode = entry.mode.unwrap_or(args.download_mode);
+        let target_dir = entry.target_dir.as_deref().unwrap_or(args.download.target_dir());
+        if let Err(x) = download_url(mode, &url, target_dir, &entry_options, &mut already_downloaded) {
+            eprintln!("Scraper error: {}", x);
+            failed.push((url, x));
+        }
+    }
+
+    println!(
+        "Finished {} entries: {} succeeded, {} failed.",
+        entries.len(),
+        entries.len() - failed.len(),
+        failed.len()
+    );
+    for (url, error) in &failed {
+        println!("  Failed: {url}: {error}");
+    }
+    if !failed.is_empty() {
+        std::process::exit(1);
     }
     Ok(())
 }
+
+/// Downloads the book or issues at a URL in the given mode.
+fn download_url(
+    mode: DownloadMode,
+    url: &str,
+    target_dir: &str,
+    options: &scraper::ScraperOptions,
+    already_downloaded: &mut HashSet<String>,
+) -> gbscraper::error::Result<()> {
+    match mode {
+        DownloadMode::Single => {
+            scraper::download_issue_skip_downloaded(url, target_dir, options, Some(already_downloaded)).map(|_| ())
+        }
+        DownloadMode::Period => scraper::download_period(url, target_dir, options, already_downloaded),
+        DownloadMode::Full => scraper::download_all(url, target_dir, options, already_downloaded),
+    }
+}